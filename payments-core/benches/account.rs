@@ -0,0 +1,116 @@
+/*!
+Benchmarks of applying transactions, along every path of a dispute, and of whole runs over generated inputs,
+reported as transactions per second.
+*/
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use payments_core::{
+    load_csv_transactions, report_ledger, Account, Currency, Engine, MemorySink, Policy,
+    Transaction, Tx,
+};
+use payments_gen::{write_csv, Settings};
+use std::collections::BTreeSet;
+
+const TRANSACTIONS: Tx = 10_000;
+
+/// Returns an account holding `TRANSACTIONS` deposits. Chargebacks don't freeze it, so that every dispute of
+/// them can be charged back.
+fn funded() -> Account {
+    let policy = Policy {
+        freeze_reasons: Some(BTreeSet::new()),
+        ..Policy::default()
+    };
+    let mut account = Account::with_policy(1, policy);
+    for tx in 1..=TRANSACTIONS {
+        account
+            .process(Transaction::Deposit(1, tx, 10.0, Currency::EUR))
+            .expect("failed depositing");
+    }
+    account
+}
+
+/// Benchmarks applying `transactions` to the account given by `setup`.
+fn bench(
+    c: &mut Criterion,
+    name: &str,
+    setup: impl Fn() -> Account,
+    transactions: Vec<Transaction>,
+) {
+    let mut group = c.benchmark_group("account");
+    group.throughput(Throughput::Elements(transactions.len() as u64));
+    group.bench_function(name, |b| {
+        b.iter_batched(
+            || (setup(), transactions.clone()),
+            |(mut account, transactions)| {
+                for tx in transactions {
+                    account.process(tx).expect("failed processing transaction");
+                }
+                account
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn processing(c: &mut Criterion) {
+    let txs = || 1..=TRANSACTIONS;
+
+    let deposits = txs()
+        .map(|tx| Transaction::Deposit(1, tx, 10.0, Currency::EUR))
+        .collect();
+    bench(c, "deposit", || Account::new(1), deposits);
+
+    let withdrawals = txs()
+        .map(|tx| Transaction::Withdrawal(1, TRANSACTIONS + tx, 5.0, Currency::EUR))
+        .collect();
+    bench(c, "withdrawal", funded, withdrawals);
+
+    let disputes = txs().map(|tx| Transaction::Dispute(1, tx, 0.0, None));
+    bench(c, "dispute", funded, disputes.clone().collect());
+
+    let settle = |settlement: fn(Tx) -> Transaction| {
+        disputes
+            .clone()
+            .zip(txs().map(settlement))
+            .flat_map(|(dispute, settlement)| [dispute, settlement])
+            .collect()
+    };
+    let resolves = settle(|tx| Transaction::Resolve(1, tx, 0.0, None));
+    bench(c, "dispute_resolve", funded, resolves);
+    let chargebacks = settle(|tx| Transaction::Chargeback(1, tx, 0.0, None));
+    bench(c, "dispute_chargeback", funded, chargebacks);
+}
+
+/// Benchmarks whole runs: reading a generated input, applying its transactions and reporting the ledger.
+fn end_to_end(c: &mut Criterion) {
+    let rows = 100_000;
+    let settings = Settings {
+        clients: 1000,
+        rows,
+        dispute_rate: 0.02,
+        ..Settings::default()
+    };
+    let mut input = Vec::new();
+    write_csv(settings, &mut input).expect("failed generating transactions");
+
+    let mut group = c.benchmark_group("end_to_end");
+    group.throughput(Throughput::Elements(rows));
+    group.sample_size(20);
+    group.bench_function("generated", |b| {
+        b.iter(|| {
+            let mut engine = Engine::new();
+            for tx in load_csv_transactions(input.as_slice()).expect("failed reading csv records") {
+                // insufficient funds and disputes of frozen accounts are rejected as in any run
+                let _ = engine.process_at(tx, 0);
+            }
+            let mut sink = MemorySink::default();
+            report_ledger(engine.ledger(), &mut sink).expect("failed reporting ledger");
+            sink
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, processing, end_to_end);
+criterion_main!(benches);
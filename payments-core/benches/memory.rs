@@ -0,0 +1,90 @@
+/*!
+Measures the memory held by the engine per million recorded transactions, counting the bytes allocated through
+the global allocator.
+
+```
+$ cargo bench -p payments-core --bench memory
+```
+*/
+
+use payments_core::{load_csv_transactions, Engine, Policy, Transaction};
+use payments_gen::{write_csv, Settings};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const MILLION: u64 = 1_000_000;
+
+/// Allocator counting the bytes currently allocated along with their peak.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Applies the transactions to a new engine, reporting the bytes it holds once they are recorded and at its
+/// peak, per million transactions applied.
+fn measure(name: &str, transactions: &[Transaction]) {
+    // chargebacks don't freeze the accounts, which would reject every later transaction of their clients
+    let policy = Policy {
+        freeze_reasons: Some(BTreeSet::new()),
+        ..Policy::default()
+    };
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+
+    let mut engine = Engine::new().with_policy(policy);
+    let applied = transactions
+        .iter()
+        .filter(|tx| engine.process_at((*tx).clone(), 0).is_ok())
+        .count();
+    let held = ALLOCATED.load(Ordering::Relaxed) - before;
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    drop(engine);
+
+    let per_million = |bytes: usize| bytes as f64 * MILLION as f64 / applied as f64;
+    println!(
+        "{}: {} of {} transactions applied, {:.1} MiB held and {:.1} MiB at peak per million ({:.0} bytes each)",
+        name,
+        applied,
+        transactions.len(),
+        per_million(held) / (1024.0 * 1024.0),
+        per_million(peak) / (1024.0 * 1024.0),
+        held as f64 / applied as f64,
+    );
+}
+
+fn main() {
+    for (name, dispute_rate) in [("deposits and withdrawals", 0.0), ("with disputes", 0.05)] {
+        let settings = Settings {
+            clients: 1000,
+            rows: MILLION,
+            dispute_rate,
+            ..Settings::default()
+        };
+        let mut input = Vec::new();
+        write_csv(settings, &mut input).expect("failed generating transactions");
+        let transactions =
+            load_csv_transactions(input.as_slice()).expect("failed reading csv records");
+        drop(input);
+        measure(name, &transactions);
+    }
+}
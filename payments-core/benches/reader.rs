@@ -0,0 +1,57 @@
+/*!
+Benchmarks of parsing CSV inputs into transactions, reported as bytes and transactions per second.
+*/
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use payments_core::{load_csv_transactions, load_csv_transactions_with, ReaderConfig};
+use payments_gen::{write_csv, Settings};
+
+/// Returns a generated input of `rows` transactions without invalid rows.
+fn input(rows: u64) -> Vec<u8> {
+    let settings = Settings {
+        rows,
+        dispute_rate: 0.02,
+        ..Settings::default()
+    };
+    let mut input = Vec::new();
+    write_csv(settings, &mut input).expect("failed generating transactions");
+    input
+}
+
+fn parsing(c: &mut Criterion) {
+    let rows = 100_000;
+    let input = input(rows);
+
+    let mut group = c.benchmark_group("parsing");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("load_csv_transactions", |b| {
+        b.iter(|| load_csv_transactions(input.as_slice()).expect("failed reading csv records"))
+    });
+    for threads in [2, 4] {
+        let config = ReaderConfig {
+            threads,
+            ..ReaderConfig::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::new("threads", threads),
+            &config,
+            |b, config| {
+                b.iter(|| {
+                    load_csv_transactions_with(input.as_slice(), config)
+                        .expect("failed reading csv records")
+                })
+            },
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("parsing_transactions");
+    group.throughput(Throughput::Elements(rows));
+    group.bench_function("load_csv_transactions", |b| {
+        b.iter(|| load_csv_transactions(input.as_slice()).expect("failed reading csv records"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parsing);
+criterion_main!(benches);
@@ -1,32 +1,151 @@
 // use clap::{Parser, Subcommand};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use super::policy::{Policy, RestoreFunds};
+pub use crate::currency::Currency;
+use crate::fx::Conversion;
 
 pub type Tx = u32;
 pub type ClientId = u16;
 pub type Amount = f64;
+/// Seconds elapsed since the Unix epoch.
+pub type Timestamp = u64;
+/// Reason code of a dispute or chargeback, e.g. `fraud` or `item-not-received`.
+pub type Reason = String;
 
 /// A Transaction represents operations that the user can request to the payment system
 ///
-/// There are 5 types of transactions that the system can handle:
+/// There are 11 types of transactions that the system can handle:
 /// - deposit: it's a credit to the client's asset account
 /// - withdraw: it's a debit to the client's asset account,
 /// - dispute: represents a client's claim that a transaction was erroneous and should be reversed.
 /// - resolve: represents a resolution to a dispute, releasing the associated held funds.
 /// - chargeback: it's the final state of a dispute and represents the client reversing a Deserialize, Debug, PartialEq, Clone)]
+/// - authorize: reserves funds of the client's asset account that can be captured later on.
+/// - capture: it's a debit of funds previously reserved by an authorization.
+/// - release: cancels an authorization, making its remaining reserved funds available again.
+/// - refund: it's a debit returning part or all of the funds of a previous deposit.
+/// - interest: it's a credit of the interest accrued by the client's funds, which cannot be disputed.
+/// - representment: the merchant re-presents a charged-back transaction with evidence, reversing the chargeback.
+///
+/// Deposits, withdrawals and authorizations state the currency of their amount, the rest of transactions act in the
+/// currency of the transaction they refer to. Disputes, resolutions and chargebacks may carry a reason code.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Transaction {
     /// A deposit is a credit to the client's asset account
-    Deposit(ClientId, Tx, Amount),
+    Deposit(ClientId, Tx, Amount, Currency),
     /// A withdraw is a debit to the client's asset account,
-    Withdrawal(ClientId, Tx, Amount),
+    Withdrawal(ClientId, Tx, Amount, Currency),
     /// A dispute represents a client's claim that a transaction was erroneous and should be reversed.
-    Dispute(ClientId, Tx, Amount),
+    Dispute(ClientId, Tx, Amount, Option<Reason>),
     /// A resolve represents a resolution to a dispute, releasing the associated held funds.
-    Resolve(ClientId, Tx, Amount),
+    Resolve(ClientId, Tx, Amount, Option<Reason>),
     /// A chargeback is the final state of a dispute and represents the client reversing a transaction.
-    Chargeback(ClientId, Tx, Amount),
+    Chargeback(ClientId, Tx, Amount, Option<Reason>),
+    /// An authorize reserves funds moving them from available to authorized.
+    Authorize(ClientId, Tx, Amount, Currency),
+    /// A capture debits part or all of the funds reserved by the authorization it refers to.
+    Capture(ClientId, Tx, Amount),
+    /// A release cancels the authorization it refers to, making the funds not captured available again.
+    Release(ClientId, Tx, Amount),
+    /// A refund debits part or all of the funds of the deposit it refers to.
+    Refund(ClientId, Tx, Amount),
+    /// An interest credits the interest accrued by the client's funds in a currency.
+    Interest(ClientId, Tx, Amount, Currency),
+    /// A representment reverses the chargeback of the transaction it refers to, restoring its funds.
+    Representment(ClientId, Tx, Amount),
+}
+
+impl Transaction {
+    /// Returns the id of the client that requested the transaction.
+    pub fn client_id(&self) -> ClientId {
+        match self {
+            Transaction::Deposit(client_id, _, _, _)
+            | Transaction::Withdrawal(client_id, _, _, _)
+            | Transaction::Dispute(client_id, _, _, _)
+            | Transaction::Resolve(client_id, _, _, _)
+            | Transaction::Chargeback(client_id, _, _, _)
+            | Transaction::Authorize(client_id, _, _, _)
+            | Transaction::Capture(client_id, _, _)
+            | Transaction::Release(client_id, _, _)
+            | Transaction::Refund(client_id, _, _)
+            | Transaction::Interest(client_id, _, _, _)
+            | Transaction::Representment(client_id, _, _) => *client_id,
+        }
+    }
+
+    /// Returns the transaction id, for disputes, resolutions, chargebacks, representments, captures, releases and
+    /// refunds it's the id of the transaction they refer to.
+    pub fn id(&self) -> Tx {
+        match self {
+            Transaction::Deposit(_, tx, _, _)
+            | Transaction::Withdrawal(_, tx, _, _)
+            | Transaction::Dispute(_, tx, _, _)
+            | Transaction::Resolve(_, tx, _, _)
+            | Transaction::Chargeback(_, tx, _, _)
+            | Transaction::Authorize(_, tx, _, _)
+            | Transaction::Capture(_, tx, _)
+            | Transaction::Release(_, tx, _)
+            | Transaction::Refund(_, tx, _)
+            | Transaction::Interest(_, tx, _, _)
+            | Transaction::Representment(_, tx, _) => *tx,
+        }
+    }
+
+    /// Returns the reason code of disputes, resolutions and chargebacks, if any.
+    pub fn reason(&self) -> Option<&Reason> {
+        match self {
+            Transaction::Dispute(_, _, _, reason)
+            | Transaction::Resolve(_, _, _, reason)
+            | Transaction::Chargeback(_, _, _, reason) => reason.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the name used for the transaction type in the CSV input.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Transaction::Deposit(..) => "deposit",
+            Transaction::Withdrawal(..) => "withdrawal",
+            Transaction::Dispute(..) => "dispute",
+            Transaction::Resolve(..) => "resolve",
+            Transaction::Chargeback(..) => "chargeback",
+            Transaction::Authorize(..) => "authorize",
+            Transaction::Capture(..) => "capture",
+            Transaction::Release(..) => "release",
+            Transaction::Refund(..) => "refund",
+            Transaction::Interest(..) => "interest",
+            Transaction::Representment(..) => "representment",
+        }
+    }
+}
+
+/// The funds of an account in a single currency.
+///
+/// The `total` funds are always the sum of the `available`, `held` and `authorized` funds.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+pub struct Balance {
+    /// Funds that can be withdrawn.
+    pub available: Amount,
+    /// Funds held by disputes.
+    pub held: Amount,
+    /// Funds reserved by authorizations.
+    pub authorized: Amount,
+    /// Total funds of the account.
+    pub total: Amount,
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.available, self.held, self.authorized, self.total
+        )
+    }
 }
 
 /// An account belongs to a unique client and it used for tracking all of the user's transactions.
@@ -35,6 +154,9 @@ pub enum Transaction {
 ///
 /// An account can track all of the history of transactions and disputes that are currently active.
 ///
+/// The funds of the account are kept in a separate `Balance` for every currency the client operates in, which are
+/// stored in the `balances` field.
+///
 /// It uses its internal field called `records` for storing all transactions that have been already executed.
 /// Transactions which are being disputed are removed from the `records` field and transfered to the `dispute` field.
 /// Both `records` and `dispute` are implemented using HashMap<Tx,Transaction>, which ensures very fast lookups due
@@ -45,16 +167,44 @@ pub enum Transaction {
 /// - Transaction::Resolve: the dispute is cancelled and it won't take any effect, held funds are recovered.
 /// - Transaction::Chargeback: the disputed is accepted and a previous deposit or withdrawal will be reversed.
 ///
+/// The reason code given by a dispute, or by its chargeback, is kept in the `reasons` field.
+///
+/// A chargeback freezes the account, unless the `Policy` only freezes on other reasons, and moves the transaction
+/// to the `charged_back` field. The merchant can still
+/// re-present it with a Transaction::Representment, which moves it to the `represented` field and restores the funds
+/// removed by the chargeback following the account's `Policy`. Every chargeback that froze the account is kept in
+/// the `freeze_causes` field, and the policy can unfreeze the account once all of them have been represented.
+///
+/// Disputes always act in the currency of the disputed transaction. Withdrawals funded from a balance in another
+/// currency keep their `Conversion` in the `conversions` field, so disputing them reverses the funds taken from that
+/// balance at the original rate.
+///
+/// Authorizations reserve funds in their own `authorized` bucket, which is kept apart from the funds `held` by disputes.
+/// The funds that are still reserved by every open authorization are tracked in the `authorizations` field, so captures
+/// can be checked against what was authorized. An authorization is closed once all of its funds have been captured or
+/// when it's released.
+///
+/// Refunds are linked to the deposit they return funds from, the amount refunded of every deposit is tracked in the
+/// `refunded` field so a deposit cannot be refunded more than once in full, and the refunded funds cannot be disputed.
+///
+/// Every transaction that has been executed is appended to the account's `history`, in the same order it was executed.
 ///
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Account {
     client_id: ClientId,
-    available: Amount,
-    held: Amount,
-    total: Amount,
+    balances: BTreeMap<Currency, Balance>,
     frozen: bool,
     records: HashMap<Tx, Transaction>,
     disputed: HashMap<Tx, Transaction>,
+    authorizations: HashMap<Tx, (Amount, Currency)>,
+    refunded: HashMap<Tx, Amount>,
+    conversions: HashMap<Tx, Conversion>,
+    charged_back: HashMap<Tx, Transaction>,
+    represented: HashMap<Tx, Transaction>,
+    freeze_causes: BTreeSet<Tx>,
+    reasons: HashMap<Tx, Reason>,
+    policy: Policy,
+    history: Vec<Transaction>,
 }
 
 impl Account {
@@ -62,56 +212,139 @@ impl Account {
     ///
     /// TODO: not checking if the client_id is valid
     pub fn new(client_id: ClientId) -> Account {
+        Account::with_policy(client_id, Policy::default())
+    }
+
+    /// Create an empty account that follows the given policy.
+    pub fn with_policy(client_id: ClientId, policy: Policy) -> Account {
         Account {
-            client_id: client_id,
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
+            client_id,
+            balances: BTreeMap::new(),
             frozen: false,
             records: HashMap::new(),
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy,
+            history: Vec::new(),
         }
     }
 
     /// Evaluates and executes a Transaction.
     ///
     /// The transaction should have a valid client id matching the account's client id. Transactions cannot
-    /// be executed if the account is frozen/locked.
+    /// be executed if the account is frozen/locked, except for representments of the chargebacks that froze it.
     pub fn process(&mut self, tx: Transaction) -> Result<()> {
-        match tx {
-            Transaction::Deposit(client_id, _, _)
-            | Transaction::Withdrawal(client_id, _, _)
-            | Transaction::Dispute(client_id, _, _)
-            | Transaction::Resolve(client_id, _, _)
-            | Transaction::Chargeback(client_id, _, _) => {
-                self.verify_transaction_valid(client_id)?
-            }
-        };
+        self.verify_transaction_valid(&tx)?;
 
         match tx {
-            Transaction::Deposit(_, tx, amount) => self.deposit(tx, amount)?,
-            Transaction::Withdrawal(_, tx, amount) => self.withdrawal(tx, amount)?,
-            Transaction::Dispute(_, tx, _) => self.dispute(tx)?,
-            Transaction::Resolve(_, tx, _) => self.resolve(tx)?,
-            Transaction::Chargeback(_, tx, _) => self.chargeback(tx)?,
+            Transaction::Deposit(_, tx, amount, currency) => self.deposit(tx, amount, currency)?,
+            Transaction::Withdrawal(_, tx, amount, currency) => {
+                self.withdrawal(tx, amount, currency)?
+            }
+            Transaction::Dispute(_, tx, _, ref reason) => self.dispute(tx, reason.clone())?,
+            Transaction::Resolve(_, tx, _, _) => self.resolve(tx)?,
+            Transaction::Chargeback(_, tx, _, ref reason) => self.chargeback(tx, reason.clone())?,
+            Transaction::Authorize(_, tx, amount, currency) => {
+                self.authorize(tx, amount, currency)?
+            }
+            Transaction::Capture(_, tx, amount) => self.capture(tx, amount)?,
+            Transaction::Release(_, tx, _) => self.release(tx)?,
+            Transaction::Refund(_, tx, amount) => self.refund(tx, amount)?,
+            Transaction::Interest(_, tx, amount, currency) => {
+                self.deposit(tx, amount, currency)? // interest is never recorded, so it cannot be disputed
+            }
+            Transaction::Representment(_, tx, amount) => self.representment(tx, amount)?,
         };
 
         match tx {
-            Transaction::Deposit(_, txid, _) | Transaction::Withdrawal(_, txid, _) => {
+            Transaction::Deposit(_, txid, _, _) | Transaction::Withdrawal(_, txid, _, _) => {
                 self.records.insert(txid, tx.clone());
             }
             _ => (),
         };
+        self.history.push(tx);
+
+        Ok(())
+    }
+
+    /// Executes a withdrawal funded from the account's balance in another currency.
+    ///
+    /// The `conversion` states the currency and the amount debited for the withdrawal, and it's kept so a later
+    /// dispute of the withdrawal acts on the same funds.
+    pub fn process_converted(&mut self, tx: Transaction, conversion: Conversion) -> Result<()> {
+        self.verify_transaction_valid(&tx)?;
+
+        let txid = match tx {
+            Transaction::Withdrawal(_, txid, amount, currency)
+                if currency == conversion.to && amount == conversion.converted =>
+            {
+                txid
+            }
+            _ => return Err(anyhow!("conversion failed, not matching the withdrawal.")),
+        };
+        self.withdrawal(txid, conversion.amount, conversion.from)?;
 
+        self.conversions.insert(txid, conversion);
+        self.records.insert(txid, tx.clone());
+        self.history.push(tx);
         Ok(())
     }
 
-    fn verify_transaction_valid(&self, client_id: ClientId) -> Result<()> {
-        if self.frozen {
+    /// Returns the conversion that funded a withdrawal, if any.
+    pub fn conversion(&self, tx: Tx) -> Option<&Conversion> {
+        self.conversions.get(&tx)
+    }
+
+    /// Returns the reason code given for disputing a transaction, if any.
+    pub fn reason(&self, tx: Tx) -> Option<&Reason> {
+        self.reasons.get(&tx)
+    }
+
+    /// Returns all of the transactions executed by the account, in the order they were executed.
+    pub fn history(&self) -> &[Transaction] {
+        &self.history
+    }
+
+    /// Returns the id of the client the account belongs to.
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+
+    /// Returns whether the account is frozen/locked.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Returns the funds of the account in a currency.
+    pub fn balance(&self, currency: Currency) -> Balance {
+        self.balances.get(&currency).copied().unwrap_or_default()
+    }
+
+    /// Returns the balance of every currency the account operates in, sorted by currency.
+    ///
+    /// An account that never held any funds reports an empty balance in the default currency.
+    pub fn balances(&self) -> Vec<(Currency, Balance)> {
+        if self.balances.is_empty() {
+            return vec![(Currency::default(), Balance::default())];
+        }
+        self.balances
+            .iter()
+            .map(|(currency, balance)| (*currency, *balance))
+            .collect()
+    }
+
+    fn verify_transaction_valid(&self, tx: &Transaction) -> Result<()> {
+        if self.frozen && !matches!(tx, Transaction::Representment(..)) {
             return Err(anyhow!("Transaction failed because account is frozen!"));
         }
 
-        if client_id != self.client_id {
+        if tx.client_id() != self.client_id {
             return Err(anyhow!(
                 "Transaction failed! not matching the account's client id."
             ));
@@ -119,48 +352,59 @@ impl Account {
         Ok(())
     }
 
-    fn deposit(&mut self, _tx: Tx, amount: Amount) -> Result<()> {
-        self.available += amount;
-        self.total += amount;
+    fn deposit(&mut self, _tx: Tx, amount: Amount, currency: Currency) -> Result<()> {
+        let balance = self.balances.entry(currency).or_default();
+        balance.available += amount;
+        balance.total += amount;
         Ok(())
     }
 
-    fn withdrawal(&mut self, _tx: Tx, amount: Amount) -> Result<()> {
-        if self.available < amount {
+    fn withdrawal(&mut self, _tx: Tx, amount: Amount, currency: Currency) -> Result<()> {
+        let balance = self.balances.entry(currency).or_default();
+        if balance.available < amount {
             return Err(anyhow!("withdrawal failed, insuficcient funds."));
         }
-        if self.total < amount {
+        if balance.total < amount {
             return Err(anyhow!("withdrawal failed, insuficcient funds."));
         }
-        self.available -= amount;
-        self.total -= amount;
+        balance.available -= amount;
+        balance.total -= amount;
         Ok(())
     }
 
-    fn dispute(&mut self, tx: Tx) -> Result<()> {
+    fn dispute(&mut self, tx: Tx, reason: Option<Reason>) -> Result<()> {
         if !self.records.contains_key(&tx) {
             return Err(anyhow!("dispute failed, not a valid transaction id."));
         }
 
         let disputed_transaction = &self.records[&tx];
+        let (amount, currency) = self.disputed_funds(tx, disputed_transaction);
 
         match disputed_transaction {
-            Transaction::Deposit(_, _, amount) => {
-                if self.available < *amount {
+            Transaction::Deposit(..) => {
+                if amount <= 0.0 {
+                    return Err(anyhow!("dispute failed, deposit has been fully refunded."));
+                }
+                let balance = self.balances.entry(currency).or_default();
+                if balance.available < amount {
                     return Err(anyhow!("dispute failed, insuficcient funds."));
                 }
-                self.available -= amount;
-                self.held += amount; // no need to update total since we move amout from available to held
+                balance.available -= amount;
+                balance.held += amount; // no need to update total since we move amout from available to held
             }
-            Transaction::Withdrawal(_, _, amount) => {
-                self.held += amount;
-                self.total += amount; // we need to update the total, since this amount was not in available nor in held previously
+            Transaction::Withdrawal(..) => {
+                let balance = self.balances.entry(currency).or_default();
+                balance.held += amount;
+                balance.total += amount; // we need to update the total, since this amount was not in available nor in held previously
             }
             _ => return Err(anyhow!("dispute failed, transaction referenced not valid.")),
         };
 
         self.disputed.insert(tx, disputed_transaction.clone());
         self.records.remove(&tx); // cannot dispute more than once the same transaction
+        if let Some(reason) = reason {
+            self.reasons.insert(tx, reason);
+        }
 
         Ok(())
     }
@@ -172,26 +416,29 @@ impl Account {
         }
 
         let disputed_transaction = &self.disputed[&tx];
+        let (amount, currency) = self.disputed_funds(tx, disputed_transaction);
         match disputed_transaction {
-            Transaction::Deposit(_, _, amount) => {
+            Transaction::Deposit(..) => {
                 // cancel the deposit dispute
-                if self.held < *amount {
+                let balance = self.balances.entry(currency).or_default();
+                if balance.held < amount {
                     return Err(anyhow!(
                         "resolving dispute failed, insuficcient held funds."
                     ));
                 }
-                self.held -= amount;
-                self.available += amount;
+                balance.held -= amount;
+                balance.available += amount;
             }
-            Transaction::Withdrawal(_, _, amount) => {
+            Transaction::Withdrawal(..) => {
                 // cancel the withdrawal dispute
-                if self.held < *amount {
+                let balance = self.balances.entry(currency).or_default();
+                if balance.held < amount {
                     return Err(anyhow!(
                         "resolving dispute failed, insuficcient held funds."
                     ));
                 }
-                self.held -= amount;
-                self.total -= amount;
+                balance.held -= amount;
+                balance.total -= amount;
             }
             _ => (), // never reached since disputed transactions are only deposits and withdrawals
         };
@@ -200,50 +447,184 @@ impl Account {
         Ok(())
     }
 
-    fn chargeback(&mut self, tx: Tx) -> Result<()> {
+    fn chargeback(&mut self, tx: Tx, reason: Option<Reason>) -> Result<()> {
         // dispute was successful, apply charge
         if !self.disputed.contains_key(&tx) {
             return Err(anyhow!("ignoring chargeback, not a valid transaction id."));
         }
 
         let disputed_transaction = &self.disputed[&tx];
+        let (amount, currency) = self.disputed_funds(tx, disputed_transaction);
         match disputed_transaction {
-            Transaction::Deposit(_, _, amount) | Transaction::Withdrawal(_, _, amount) => {
-                if self.held < *amount {
+            Transaction::Deposit(..) | Transaction::Withdrawal(..) => {
+                let balance = self.balances.entry(currency).or_default();
+                if balance.held < amount {
                     return Err(anyhow!("chargeback failed, insuficcient held funds."));
                 }
-                if self.total < *amount {
+                if balance.total < amount {
                     return Err(anyhow!("chargeback failed, insuficcient total funds."));
                 }
-                self.held -= amount;
-                self.total -= amount;
-                self.frozen = true; // transactions might be fraudulatent threfore account is frozen.
+                balance.held -= amount;
+                balance.total -= amount;
             }
             _ => (), // never reached since disputed transactions are only deposits and withdrawals
         };
-        self.disputed.remove(&tx);
+        if let Some(reason) = reason {
+            self.reasons.insert(tx, reason); // the chargeback reason takes over the dispute's one
+        }
+        if self.policy.freezes(self.reasons.get(&tx)) {
+            self.frozen = true; // transactions might be fraudulatent threfore account is frozen.
+            self.freeze_causes.insert(tx);
+        }
+        if let Some(charged_back) = self.disputed.remove(&tx) {
+            self.charged_back.insert(tx, charged_back);
+        }
+        Ok(())
+    }
+
+    fn representment(&mut self, tx: Tx, amount: Amount) -> Result<()> {
+        // the merchant re-presented the transaction, reverse the chargeback
+        let charged_back = match self.charged_back.get(&tx) {
+            Some(charged_back) => charged_back,
+            None => return Err(anyhow!("representment failed, not a valid chargeback id.")),
+        };
+
+        let (charged, currency) = self.disputed_funds(tx, charged_back);
+        let restored = match self.policy.restore_funds {
+            RestoreFunds::Full => charged,
+            RestoreFunds::Requested => amount.min(charged),
+        };
+        let balance = self.balances.entry(currency).or_default();
+        balance.available += restored;
+        balance.total += restored;
+
+        if let Some(charged_back) = self.charged_back.remove(&tx) {
+            self.represented.insert(tx, charged_back);
+        }
+        self.freeze_causes.remove(&tx);
+        if self.policy.unfreeze_on_representment && self.freeze_causes.is_empty() {
+            self.frozen = false; // the represented chargeback was the only reason for freezing the account
+        }
+        Ok(())
+    }
+
+    /// Returns the amount and currency put on hold when disputing a transaction.
+    ///
+    /// Funds already refunded cannot be disputed, and converted withdrawals are disputed in the currency they were
+    /// funded from.
+    fn disputed_funds(&self, tx: Tx, transaction: &Transaction) -> (Amount, Currency) {
+        match transaction {
+            Transaction::Deposit(_, _, amount, currency) => {
+                (amount - self.refunded.get(&tx).unwrap_or(&0.0), *currency)
+            }
+            Transaction::Withdrawal(_, _, amount, currency) => match self.conversions.get(&tx) {
+                Some(conversion) => (conversion.amount, conversion.from),
+                None => (*amount, *currency),
+            },
+            _ => (0.0, Currency::default()),
+        }
+    }
+
+    fn refund(&mut self, tx: Tx, amount: Amount) -> Result<()> {
+        let (deposited, currency) = match self.records.get(&tx) {
+            Some(Transaction::Deposit(_, _, deposited, currency)) => (*deposited, *currency),
+            _ => return Err(anyhow!("refund failed, not a valid deposit id.")),
+        };
+        let refunded = self.refunded.get(&tx).copied().unwrap_or(0.0);
+        if amount > deposited - refunded {
+            return Err(anyhow!(
+                "refund failed, amount exceeds the unrefunded funds of the deposit."
+            ));
+        }
+        let balance = self.balances.entry(currency).or_default();
+        if balance.available < amount {
+            return Err(anyhow!("refund failed, insuficcient funds."));
+        }
+        balance.available -= amount;
+        balance.total -= amount;
+        self.refunded.insert(tx, refunded + amount);
+        Ok(())
+    }
+
+    fn authorize(&mut self, tx: Tx, amount: Amount, currency: Currency) -> Result<()> {
+        if self.authorizations.contains_key(&tx) {
+            return Err(anyhow!(
+                "authorization failed, transaction id already authorized."
+            ));
+        }
+        let balance = self.balances.entry(currency).or_default();
+        if balance.available < amount {
+            return Err(anyhow!("authorization failed, insuficcient funds."));
+        }
+        balance.available -= amount;
+        balance.authorized += amount; // no need to update total since we move amount from available to authorized
+        self.authorizations.insert(tx, (amount, currency));
+        Ok(())
+    }
+
+    fn capture(&mut self, tx: Tx, amount: Amount) -> Result<()> {
+        let (reserved, currency) = match self.authorizations.get(&tx) {
+            Some(authorization) => *authorization,
+            None => return Err(anyhow!("capture failed, not a valid authorization id.")),
+        };
+        if amount > reserved {
+            return Err(anyhow!(
+                "capture failed, amount exceeds the authorized funds."
+            ));
+        }
+        let balance = self.balances.entry(currency).or_default();
+        balance.authorized -= amount;
+        balance.total -= amount;
+
+        if amount < reserved {
+            self.authorizations
+                .insert(tx, (reserved - amount, currency)); // partial capture, authorization is still open
+        } else {
+            self.authorizations.remove(&tx);
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, tx: Tx) -> Result<()> {
+        let (reserved, currency) = match self.authorizations.remove(&tx) {
+            Some(authorization) => authorization,
+            None => return Err(anyhow!("release failed, not a valid authorization id.")),
+        };
+        let balance = self.balances.entry(currency).or_default();
+        balance.authorized -= reserved;
+        balance.available += reserved;
         Ok(())
     }
 }
 
-impl ToString for Account {
-    fn to_string(&self) -> String {
-        format!(
-            "{},{},{},{}",
-            self.available, self.held, self.total, self.frozen,
-        )
+impl fmt::Display for Account {
+    /// Formats the balance of every currency as `currency,available,held,authorized,total,locked`, separated by `;`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (currency, balance)) in self.balances().iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+            write!(f, "{},{},{}", currency, balance, self.frozen)?;
+        }
+        Ok(())
     }
 }
 
 impl PartialEq for Account {
+    // the history is left out, accounts are equal when they hold the same funds and the same open transactions
     fn eq(&self, other: &Self) -> bool {
         self.client_id == other.client_id
-            && self.available == other.available
-            && self.held == other.held
-            && self.total == other.total
+            && self.balances == other.balances
             && self.frozen == other.frozen
             && self.records == other.records
             && self.disputed == other.disputed
+            && self.authorizations == other.authorizations
+            && self.refunded == other.refunded
+            && self.conversions == other.conversions
+            && self.charged_back == other.charged_back
+            && self.represented == other.represented
+            && self.freeze_causes == other.freeze_causes
+            && self.reasons == other.reasons
     }
 }
 
@@ -251,10 +632,28 @@ impl PartialEq for Account {
 mod tests {
     use super::*;
 
+    /// Balances of an account that only operates in the default currency
+    fn balances(
+        available: Amount,
+        held: Amount,
+        authorized: Amount,
+        total: Amount,
+    ) -> BTreeMap<Currency, Balance> {
+        BTreeMap::from([(
+            Currency::EUR,
+            Balance {
+                available,
+                held,
+                authorized,
+                total,
+            },
+        )])
+    }
+
     #[test]
     fn test_transaction_not_matching_accounts_client_id() {
         let mut account = Account::new(12);
-        let tx = Transaction::Deposit(4, 1, 1.0); // deposit amount 1.0 for client 12, with tx(Transaction Id) 1
+        let tx = Transaction::Deposit(4, 1, 1.0, Currency::EUR); // deposit amount 1.0 for client 12, with tx(Transaction Id) 1
         let res = account.process(tx);
 
         assert_eq!(
@@ -265,12 +664,19 @@ mod tests {
             account,
             Account {
                 client_id: 12,
-                available: 0.0,
-                held: 0.0,
-                total: 0.0,
+                balances: BTreeMap::new(), // no funds in any currency
                 frozen: false,
                 records: HashMap::new(), // no transaction recorded
                 disputed: HashMap::new(),
+                authorizations: HashMap::new(),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
             }
         );
     }
@@ -278,7 +684,7 @@ mod tests {
     #[test]
     fn test_deposit() {
         let mut account = Account::new(12);
-        let tx = Transaction::Deposit(12, 1, 1.0); // deposit amount 1.0 for client 12, with tx(Transaction Id) 1
+        let tx = Transaction::Deposit(12, 1, 1.0, Currency::EUR); // deposit amount 1.0 for client 12, with tx(Transaction Id) 1
         let res = account.process(tx);
 
         assert!(res.is_ok());
@@ -287,12 +693,19 @@ mod tests {
             account,
             Account {
                 client_id: 12,
-                available: 1.0,
-                held: 0.0,
-                total: 1.0,
+                balances: balances(1.0, 0.0, 0.0, 1.0),
                 frozen: false,
-                records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]), // 1 transaction
+                records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]), // 1 transaction
                 disputed: HashMap::new(),
+                authorizations: HashMap::new(),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
             }
         );
     }
@@ -302,15 +715,22 @@ mod tests {
         // initialize an account with available funds to 1.0
         let mut account = Account {
             client_id: 12,
-            available: 1.0,
-            held: 0.0,
-            total: 1.0,
+            balances: balances(1.0, 0.0, 0.0, 1.0),
             frozen: false,
-            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]), // 1 transaction
+            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]), // 1 transaction
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
 
-        let tx = Transaction::Withdrawal(12, 2, 3.0); // withdawal amount 1.0 for client 12, with tx(Transaction Id) 2
+        let tx = Transaction::Withdrawal(12, 2, 3.0, Currency::EUR); // withdawal amount 1.0 for client 12, with tx(Transaction Id) 2
         let res = account.process(tx);
         assert_eq!(
             res.err().unwrap().to_string(),
@@ -321,18 +741,25 @@ mod tests {
     fn test_not_valid_transaction_id_in_dispute() {
         let mut account = Account {
             client_id: 12,
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
+            balances: balances(0.0, 0.0, 0.0, 0.0),
             frozen: false,
             records: HashMap::from([
-                (1, Transaction::Deposit(12, 1, 1.0)), // 2 transactions recorded
-                (2, Transaction::Withdrawal(12, 2, 1.0)),
+                (1, Transaction::Deposit(12, 1, 1.0, Currency::EUR)), // 2 transactions recorded
+                (2, Transaction::Withdrawal(12, 2, 1.0, Currency::EUR)),
             ]),
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
 
-        let tx = Transaction::Dispute(12, 3, 1.0); // withdawal amount 1.0 for client 12, with tx(Transaction Id) 3 does not exist
+        let tx = Transaction::Dispute(12, 3, 1.0, None); // withdawal amount 1.0 for client 12, with tx(Transaction Id) 3 does not exist
         let res = account.process(tx);
 
         assert_eq!(
@@ -344,18 +771,25 @@ mod tests {
     fn test_disputing_a_withdrawal_of_accounts_total_funds() {
         let mut account = Account {
             client_id: 12,
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
+            balances: balances(0.0, 0.0, 0.0, 0.0),
             frozen: false,
             records: HashMap::from([
-                (1, Transaction::Deposit(12, 1, 1.0)), // 2 transactions recorded
-                (2, Transaction::Withdrawal(12, 2, 1.0)),
+                (1, Transaction::Deposit(12, 1, 1.0, Currency::EUR)), // 2 transactions recorded
+                (2, Transaction::Withdrawal(12, 2, 1.0, Currency::EUR)),
             ]),
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
 
-        let tx = Transaction::Dispute(12, 2, 1.0);
+        let tx = Transaction::Dispute(12, 2, 1.0, None);
         let res = account.process(tx);
 
         assert!(res.is_ok());
@@ -363,14 +797,21 @@ mod tests {
             account,
             Account {
                 client_id: 12,
-                available: 0.0,
-                held: 1.0,
-                total: 1.0,
+                balances: balances(0.0, 1.0, 0.0, 1.0),
                 frozen: false,
                 records: HashMap::from([
-                    (1, Transaction::Deposit(12, 1, 1.0)), // 1 transactions recorded(the other one is being disputed)
+                    (1, Transaction::Deposit(12, 1, 1.0, Currency::EUR)), // 1 transactions recorded(the other one is being disputed)
                 ]),
-                disputed: HashMap::from([(2, Transaction::Withdrawal(12, 2, 1.0))]), //disputed transaction
+                disputed: HashMap::from([(2, Transaction::Withdrawal(12, 2, 1.0, Currency::EUR))]), //disputed transaction
+                authorizations: HashMap::new(),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
             }
         );
     }
@@ -378,18 +819,25 @@ mod tests {
     fn test_dispute_partial_funds_withdrawal() {
         let mut account = Account {
             client_id: 12,
-            available: 1.0,
-            held: 0.0,
-            total: 1.0,
+            balances: balances(1.0, 0.0, 0.0, 1.0),
             frozen: false,
             records: HashMap::from([
-                (1, Transaction::Deposit(12, 1, 2.0)), // 2 transactions recorded
-                (2, Transaction::Withdrawal(12, 2, 1.0)),
+                (1, Transaction::Deposit(12, 1, 2.0, Currency::EUR)), // 2 transactions recorded
+                (2, Transaction::Withdrawal(12, 2, 1.0, Currency::EUR)),
             ]),
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
 
-        let tx = Transaction::Dispute(12, 2, 1.0);
+        let tx = Transaction::Dispute(12, 2, 1.0, None);
         let res = account.process(tx);
 
         assert!(res.is_ok());
@@ -397,14 +845,21 @@ mod tests {
             account,
             Account {
                 client_id: 12,
-                available: 1.0,
-                held: 1.0,
-                total: 2.0,
+                balances: balances(1.0, 1.0, 0.0, 2.0),
                 frozen: false,
                 records: HashMap::from([
-                    (1, Transaction::Deposit(12, 1, 2.0)), // 1 transactions recorded(the other one is being disputed)
+                    (1, Transaction::Deposit(12, 1, 2.0, Currency::EUR)), // 1 transactions recorded(the other one is being disputed)
                 ]),
-                disputed: HashMap::from([(2, Transaction::Withdrawal(12, 2, 1.0))]), //disputed transaction
+                disputed: HashMap::from([(2, Transaction::Withdrawal(12, 2, 1.0, Currency::EUR))]), //disputed transaction
+                authorizations: HashMap::new(),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
             }
         );
     }
@@ -412,17 +867,24 @@ mod tests {
     fn test_disputing_a_deposit_after_no_funds_in_account() {
         let mut account = Account {
             client_id: 12,
-            available: 0.0,
-            held: 0.0,
-            total: 0.0,
+            balances: balances(0.0, 0.0, 0.0, 0.0),
             frozen: false,
             records: HashMap::from([
-                (1, Transaction::Deposit(12, 1, 1.0)), // 2 transactions recorded
-                (2, Transaction::Withdrawal(12, 2, 1.0)),
+                (1, Transaction::Deposit(12, 1, 1.0, Currency::EUR)), // 2 transactions recorded
+                (2, Transaction::Withdrawal(12, 2, 1.0, Currency::EUR)),
             ]),
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
-        let tx = Transaction::Dispute(12, 1, 0.0); // dispute deposit
+        let tx = Transaction::Dispute(12, 1, 0.0, None); // dispute deposit
         let res = account.process(tx);
 
         // this dispute should fail because after the withdrawal of all funds
@@ -436,14 +898,21 @@ mod tests {
     fn test_resolve_a_deposit_dispute() {
         let mut account = Account {
             client_id: 12,
-            available: 0.0,
-            held: 1.0,
-            total: 1.0,
+            balances: balances(0.0, 1.0, 0.0, 1.0),
             frozen: false,
-            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
-            disputed: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
+            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
+            disputed: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
-        let tx = Transaction::Resolve(12, 1, 0.0); // resolve dispute
+        let tx = Transaction::Resolve(12, 1, 0.0, None); // resolve dispute
         let res = account.process(tx);
 
         assert!(res.is_ok());
@@ -451,12 +920,19 @@ mod tests {
             account,
             Account {
                 client_id: 12,
-                available: 1.0,
-                held: 0.0,
-                total: 1.0,
+                balances: balances(1.0, 0.0, 0.0, 1.0),
                 frozen: false,
-                records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
+                records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
                 disputed: HashMap::new(),
+                authorizations: HashMap::new(),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
             }
         );
     }
@@ -465,14 +941,21 @@ mod tests {
     fn test_resolve_non_existent_dispute() {
         let mut account = Account {
             client_id: 12,
-            available: 0.0,
-            held: 1.0,
-            total: 1.0,
+            balances: balances(0.0, 1.0, 0.0, 1.0),
             frozen: false,
-            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
+            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
             disputed: HashMap::new(),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
-        let tx = Transaction::Resolve(12, 1, 0.0); // resolving a non existent dispute
+        let tx = Transaction::Resolve(12, 1, 0.0, None); // resolving a non existent dispute
         let res = account.process(tx);
         assert_eq!(
             res.err().unwrap().to_string(),
@@ -484,14 +967,21 @@ mod tests {
     fn test_chargeback_deposit() {
         let mut account = Account {
             client_id: 12,
-            available: 0.0,
-            held: 1.0,
-            total: 1.0,
+            balances: balances(0.0, 1.0, 0.0, 1.0),
             frozen: false,
-            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
-            disputed: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
+            records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
+            disputed: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
+            authorizations: HashMap::new(),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
         };
-        let tx = Transaction::Chargeback(12, 1, 0.0); // chargeback dispute
+        let tx = Transaction::Chargeback(12, 1, 0.0, None); // chargeback dispute
         let res = account.process(tx);
 
         assert!(res.is_ok());
@@ -499,14 +989,522 @@ mod tests {
             account,
             Account {
                 client_id: 12,
-                available: 0.0,
-                held: 0.0,
-                total: 0.0,
+                balances: balances(0.0, 0.0, 0.0, 0.0),
                 frozen: true,
-                records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0))]),
+                records: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
                 disputed: HashMap::new(),
+                authorizations: HashMap::new(),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::from([(1, Transaction::Deposit(12, 1, 1.0, Currency::EUR))]),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::from([1]),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
             }
         );
     }
     // TODO: add test chrageback a withdrawal transaction (happy flow)
+
+    fn charged_back_account(policy: Policy) -> Account {
+        let mut account = Account::with_policy(12, policy);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Deposit(12, 2, 3.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Dispute(12, 1, 0.0, None))
+            .unwrap();
+        account
+            .process(Transaction::Chargeback(12, 1, 0.0, None))
+            .unwrap();
+        account
+    }
+
+    #[test]
+    fn test_representment_restores_charged_back_funds() {
+        let mut account = charged_back_account(Policy::default());
+        assert_eq!(account.to_string(), "EUR,3,0,0,3,true");
+
+        let res = account.process(Transaction::Representment(12, 1, 0.0));
+        assert!(res.is_ok());
+        assert_eq!(account.to_string(), "EUR,8,0,0,8,true"); // still frozen by default
+        assert_eq!(account.history().len(), 5);
+
+        // it cannot be represented twice
+        let res = account.process(Transaction::Representment(12, 1, 0.0));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "representment failed, not a valid chargeback id."
+        );
+    }
+
+    #[test]
+    fn test_representment_requested_amount_and_unfreeze() {
+        let mut account = charged_back_account(Policy {
+            restore_funds: RestoreFunds::Requested,
+            unfreeze_on_representment: true,
+            ..Policy::default()
+        });
+        account
+            .process(Transaction::Representment(12, 1, 20.0))
+            .unwrap();
+        assert_eq!(account.to_string(), "EUR,8,0,0,8,false"); // capped at the charged-back funds
+
+        let mut account = charged_back_account(Policy {
+            restore_funds: RestoreFunds::Requested,
+            unfreeze_on_representment: true,
+            ..Policy::default()
+        });
+        account.freeze_causes.insert(2); // another chargeback still keeps it frozen
+        account
+            .process(Transaction::Representment(12, 1, 2.0))
+            .unwrap();
+        assert_eq!(account.to_string(), "EUR,5,0,0,5,true");
+    }
+
+    #[test]
+    fn test_chargeback_freezes_only_on_policy_reasons() {
+        let policy = Policy {
+            freeze_reasons: Some(BTreeSet::from(["fraud".to_string()])),
+            ..Policy::default()
+        };
+        let mut account = Account::with_policy(12, policy);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Deposit(12, 2, 3.0, Currency::EUR))
+            .unwrap();
+
+        let reason = Some("item-not-received".to_string());
+        account
+            .process(Transaction::Dispute(12, 1, 0.0, reason.clone()))
+            .unwrap();
+        account
+            .process(Transaction::Chargeback(12, 1, 0.0, None))
+            .unwrap();
+        assert_eq!(account.to_string(), "EUR,3,0,0,3,false");
+        assert_eq!(account.reason(1), reason.as_ref());
+
+        // the chargeback reason takes over the one given by the dispute
+        account
+            .process(Transaction::Dispute(12, 2, 0.0, None))
+            .unwrap();
+        account
+            .process(Transaction::Chargeback(
+                12,
+                2,
+                0.0,
+                Some("fraud".to_string()),
+            ))
+            .unwrap();
+        assert_eq!(account.to_string(), "EUR,0,0,0,0,true");
+        assert_eq!(
+            account.history()[2],
+            Transaction::Dispute(12, 1, 0.0, reason)
+        );
+    }
+
+    #[test]
+    fn test_authorize_reserves_funds() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        let res = account.process(Transaction::Authorize(12, 2, 3.0, Currency::EUR));
+
+        assert!(res.is_ok());
+        assert_eq!(
+            account,
+            Account {
+                client_id: 12,
+                balances: balances(2.0, 0.0, 3.0, 5.0),
+                frozen: false,
+                records: HashMap::from([(1, Transaction::Deposit(12, 1, 5.0, Currency::EUR))]),
+                disputed: HashMap::new(),
+                authorizations: HashMap::from([(2, (3.0, Currency::EUR))]),
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_authorize_more_funds_than_available() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 1.0, Currency::EUR))
+            .unwrap();
+        let res = account.process(Transaction::Authorize(12, 2, 3.0, Currency::EUR));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "authorization failed, insuficcient funds."
+        );
+    }
+
+    #[test]
+    fn test_partial_captures_of_an_authorization() {
+        let mut account = Account {
+            client_id: 12,
+            balances: balances(2.0, 0.0, 3.0, 5.0),
+            frozen: false,
+            records: HashMap::new(),
+            disputed: HashMap::new(),
+            authorizations: HashMap::from([(2, (3.0, Currency::EUR))]),
+            refunded: HashMap::new(),
+            conversions: HashMap::new(),
+            charged_back: HashMap::new(),
+            represented: HashMap::new(),
+            freeze_causes: BTreeSet::new(),
+            reasons: HashMap::new(),
+            policy: Policy::default(),
+            history: Vec::new(),
+        };
+
+        assert!(account.process(Transaction::Capture(12, 2, 1.0)).is_ok());
+        assert_eq!(
+            account.authorizations,
+            HashMap::from([(2, (2.0, Currency::EUR))])
+        );
+
+        assert!(account.process(Transaction::Capture(12, 2, 2.0)).is_ok());
+        assert_eq!(
+            account,
+            Account {
+                client_id: 12,
+                balances: balances(2.0, 0.0, 0.0, 2.0),
+                frozen: false,
+                records: HashMap::new(),
+                disputed: HashMap::new(),
+                authorizations: HashMap::new(), // fully captured authorizations are closed
+                refunded: HashMap::new(),
+                conversions: HashMap::new(),
+                charged_back: HashMap::new(),
+                represented: HashMap::new(),
+                freeze_causes: BTreeSet::new(),
+                reasons: HashMap::new(),
+                policy: Policy::default(),
+                history: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_capture_more_than_authorized() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Authorize(12, 2, 3.0, Currency::EUR))
+            .unwrap();
+
+        let res = account.process(Transaction::Capture(12, 2, 4.0));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "capture failed, amount exceeds the authorized funds."
+        );
+        assert_eq!(account.to_string(), "EUR,2,0,3,5,false");
+    }
+
+    #[test]
+    fn test_release_remaining_authorized_funds() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Authorize(12, 2, 3.0, Currency::EUR))
+            .unwrap();
+        account.process(Transaction::Capture(12, 2, 1.0)).unwrap();
+
+        assert!(account.process(Transaction::Release(12, 2, 0.0)).is_ok());
+        assert_eq!(account.to_string(), "EUR,4,0,0,4,false");
+
+        let res = account.process(Transaction::Capture(12, 2, 1.0)); // authorization is already closed
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "capture failed, not a valid authorization id."
+        );
+    }
+
+    #[test]
+    fn test_authorized_funds_are_reported_apart_from_held_funds() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Deposit(12, 2, 2.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Authorize(12, 3, 3.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Dispute(12, 2, 0.0, None))
+            .unwrap();
+
+        assert_eq!(account.to_string(), "EUR,2,2,3,7,false");
+    }
+
+    #[test]
+    fn test_partial_refunds_of_a_deposit() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+
+        assert!(account.process(Transaction::Refund(12, 1, 2.0)).is_ok());
+        assert!(account.process(Transaction::Refund(12, 1, 1.0)).is_ok());
+        assert_eq!(account.refunded, HashMap::from([(1, 3.0)]));
+
+        let res = account.process(Transaction::Refund(12, 1, 2.5));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "refund failed, amount exceeds the unrefunded funds of the deposit."
+        );
+        assert_eq!(account.to_string(), "EUR,2,0,0,2,false");
+    }
+
+    #[test]
+    fn test_refund_not_referencing_a_deposit() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account
+            .process(Transaction::Withdrawal(12, 2, 1.0, Currency::EUR))
+            .unwrap();
+
+        let res = account.process(Transaction::Refund(12, 2, 1.0));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "refund failed, not a valid deposit id."
+        );
+    }
+
+    #[test]
+    fn test_refunded_funds_cannot_be_disputed() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account.process(Transaction::Refund(12, 1, 2.0)).unwrap();
+
+        assert!(account
+            .process(Transaction::Dispute(12, 1, 0.0, None))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,0,3,0,3,false");
+
+        assert!(account
+            .process(Transaction::Chargeback(12, 1, 0.0, None))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,0,0,0,0,true");
+    }
+
+    #[test]
+    fn test_disputing_a_fully_refunded_deposit() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account.process(Transaction::Refund(12, 1, 5.0)).unwrap();
+
+        let res = account.process(Transaction::Dispute(12, 1, 0.0, None));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "dispute failed, deposit has been fully refunded."
+        );
+    }
+
+    #[test]
+    fn test_history_keeps_executed_transactions_in_order() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::EUR))
+            .unwrap();
+        account.process(Transaction::Refund(12, 1, 2.0)).unwrap();
+        assert!(account
+            .process(Transaction::Withdrawal(12, 2, 9.0, Currency::EUR))
+            .is_err()); // rejected, not recorded
+        account
+            .process(Transaction::Dispute(12, 1, 0.0, None))
+            .unwrap();
+
+        assert_eq!(
+            account.history(),
+            &[
+                Transaction::Deposit(12, 1, 5.0, Currency::EUR),
+                Transaction::Refund(12, 1, 2.0),
+                Transaction::Dispute(12, 1, 0.0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_balances_are_kept_per_currency() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::GBP))
+            .unwrap();
+        account
+            .process(Transaction::Deposit(12, 2, 3.0, Currency::EUR))
+            .unwrap();
+
+        let res = account.process(Transaction::Withdrawal(12, 3, 4.0, Currency::EUR));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "withdrawal failed, insuficcient funds."
+        );
+        assert!(account
+            .process(Transaction::Withdrawal(12, 4, 4.0, Currency::GBP))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,3,0,0,3,false;GBP,1,0,0,1,false");
+    }
+
+    #[test]
+    fn test_dispute_acts_in_the_transaction_currency() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 5.0, Currency::USD))
+            .unwrap();
+        account
+            .process(Transaction::Deposit(12, 2, 3.0, Currency::EUR))
+            .unwrap();
+
+        assert!(account
+            .process(Transaction::Dispute(12, 1, 0.0, None))
+            .is_ok());
+        assert_eq!(
+            account.balance(Currency::USD),
+            Balance {
+                available: 0.0,
+                held: 5.0,
+                authorized: 0.0,
+                total: 5.0
+            }
+        );
+        assert!(account
+            .process(Transaction::Chargeback(12, 1, 0.0, None))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,3,0,0,3,true;USD,0,0,0,0,true");
+    }
+
+    #[test]
+    fn test_empty_account_reports_default_currency() {
+        let account = Account::new(12);
+        assert_eq!(
+            account.balances(),
+            vec![(Currency::EUR, Balance::default())]
+        );
+    }
+
+    #[test]
+    fn test_converted_withdrawal_debits_the_funding_currency() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 10.0, Currency::EUR))
+            .unwrap();
+        let conversion = Conversion {
+            from: Currency::EUR,
+            to: Currency::USD,
+            rate: 1.25,
+            amount: 4.0,
+            converted: 5.0,
+        };
+
+        let res = account.process_converted(
+            Transaction::Withdrawal(12, 2, 5.0, Currency::USD),
+            conversion,
+        );
+        assert!(res.is_ok());
+        assert_eq!(account.to_string(), "EUR,6,0,0,6,false");
+        assert_eq!(account.conversion(2), Some(&conversion));
+    }
+
+    #[test]
+    fn test_converted_withdrawal_not_matching_the_conversion() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 10.0, Currency::EUR))
+            .unwrap();
+        let conversion = Conversion {
+            from: Currency::EUR,
+            to: Currency::USD,
+            rate: 1.25,
+            amount: 4.0,
+            converted: 5.0,
+        };
+
+        let res = account.process_converted(
+            Transaction::Withdrawal(12, 2, 5.0, Currency::GBP),
+            conversion,
+        );
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "conversion failed, not matching the withdrawal."
+        );
+    }
+
+    #[test]
+    fn test_dispute_of_converted_withdrawal_reverses_at_original_rate() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 10.0, Currency::EUR))
+            .unwrap();
+        let conversion = Conversion {
+            from: Currency::EUR,
+            to: Currency::USD,
+            rate: 1.25,
+            amount: 4.0,
+            converted: 5.0,
+        };
+        account
+            .process_converted(
+                Transaction::Withdrawal(12, 2, 5.0, Currency::USD),
+                conversion,
+            )
+            .unwrap();
+
+        assert!(account
+            .process(Transaction::Dispute(12, 2, 0.0, None))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,6,4,0,10,false");
+        assert!(account
+            .process(Transaction::Resolve(12, 2, 0.0, None))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,6,0,0,6,false");
+    }
+
+    #[test]
+    fn test_interest_cannot_be_disputed() {
+        let mut account = Account::new(12);
+        account
+            .process(Transaction::Deposit(12, 1, 10.0, Currency::EUR))
+            .unwrap();
+        assert!(account
+            .process(Transaction::Interest(12, 2, 0.5, Currency::EUR))
+            .is_ok());
+        assert_eq!(account.to_string(), "EUR,10.5,0,0,10.5,false");
+
+        let res = account.process(Transaction::Dispute(12, 2, 0.0, None));
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "dispute failed, not a valid transaction id."
+        );
+        assert_eq!(
+            account.history().last(),
+            Some(&Transaction::Interest(12, 2, 0.5, Currency::EUR))
+        );
+    }
 }
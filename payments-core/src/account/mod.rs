@@ -1,14 +1,18 @@
 mod account;
+mod policy;
 
-pub use account::{Account, Amount, ClientId, Transaction, Tx};
+pub use account::{
+    Account, Amount, Balance, ClientId, Currency, Reason, Timestamp, Transaction, Tx,
+};
+pub use policy::{Policy, RestoreFunds};
 
 #[cfg(test)]
 mod tests {
-    use super::account::{Account, Transaction};
+    use super::account::{Account, Currency, Transaction};
     #[test]
     fn test_create_transaction_for_account() {
-        let tx = Transaction::Deposit(1, 1, 1.0);
-        assert_eq!(Transaction::Deposit(1, 1, 1.0), tx);
+        let tx = Transaction::Deposit(1, 1, 1.0, Currency::EUR);
+        assert_eq!(Transaction::Deposit(1, 1, 1.0, Currency::EUR), tx);
 
         let mut account = Account::new(1);
         let res = account.process(tx);
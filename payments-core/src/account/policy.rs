@@ -0,0 +1,59 @@
+use super::account::Reason;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// How much of the funds removed by a chargeback are restored when the chargeback is represented.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum RestoreFunds {
+    /// All of the funds removed by the chargeback are restored.
+    Full,
+    /// The amount stated by the representment is restored, up to the funds removed by the chargeback.
+    Requested,
+}
+
+impl FromStr for RestoreFunds {
+    type Err = anyhow::Error;
+
+    /// Parses how funds are restored: `full` or `requested`.
+    fn from_str(restore: &str) -> anyhow::Result<RestoreFunds> {
+        match restore {
+            "full" => Ok(RestoreFunds::Full),
+            "requested" => Ok(RestoreFunds::Requested),
+            _ => Err(anyhow::anyhow!("Not a valid restore policy '{}'", restore)),
+        }
+    }
+}
+
+/// A Policy holds the rules an account follows for the outcome of its disputes.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Policy {
+    /// Funds restored to the available funds when a chargeback is represented.
+    pub restore_funds: RestoreFunds,
+    /// Whether a representment unfreezes the account when its chargeback was the only cause of the freeze.
+    pub unfreeze_on_representment: bool,
+    /// Reason codes of the chargebacks that freeze the account, when missing every chargeback freezes it.
+    pub freeze_reasons: Option<BTreeSet<Reason>>,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            restore_funds: RestoreFunds::Full,
+            unfreeze_on_representment: false,
+            freeze_reasons: None,
+        }
+    }
+}
+
+impl Policy {
+    /// Returns whether a chargeback with the given reason code freezes the account.
+    pub fn freezes(&self, reason: Option<&Reason>) -> bool {
+        match (&self.freeze_reasons, reason) {
+            (None, _) => true,
+            (Some(reasons), Some(reason)) => reasons.contains(reason),
+            (Some(_), None) => false,
+        }
+    }
+}
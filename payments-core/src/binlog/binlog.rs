@@ -0,0 +1,360 @@
+use crate::account::{Currency, Transaction};
+use crate::checkpoint::Digest;
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const MAGIC: [u8; 8] = *b"PAYTXLOG";
+/// Number of bytes at the start of a stream needed for telling whether it's a binary transaction log.
+pub const BINARY_MAGIC_LEN: usize = MAGIC.len();
+/// Version of the encoding written by `BinaryWriter`. Logs of the first version, which has no table of long
+/// reasons, are read as well.
+pub(crate) const VERSION: u16 = 2;
+/// Size in bytes of the header: magic, version, record size, reserved bytes, number of records and checksum.
+pub(crate) const HEADER_SIZE: usize = 32;
+/// Size in bytes of every record.
+pub(crate) const RECORD_SIZE: usize = 40;
+/// Longest reason stored within a record, longer ones go to the table of reasons.
+const MAX_REASON: usize = 20;
+
+const NO_REASON: u8 = 0xff;
+/// Reason length marking a reason stored in the table of reasons, the record holding its index instead.
+const LONG_REASON: u8 = 0xfe;
+
+/// Returns whether a stream starts like a binary transaction log.
+pub fn is_binary_log(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// BinaryWriter encodes transactions into a binary log of fixed-width records.
+///
+/// The log starts with a header carrying the version of the encoding, the size of the records, the number of
+/// long reasons and records and a checksum of them, which are written once the writer is finished:
+///
+/// ```text
+/// header: magic "PAYTXLOG" | version u16 | record size u16 | long reasons u32 | records u64 | checksum u64
+/// record: type u8 | reason length u8 | client u16 | tx u32 | amount f64 | currency [u8; 3] | reason [u8; 20] | pad
+/// reasons: (length u32 | reason)*
+/// ```
+///
+/// Reasons longer than 20 bytes are stored once in the table of reasons following the records, and their records
+/// hold the index of the reason in the table instead. Numbers are little endian, and the checksum is the FNV-1a
+/// hash of all of the records and of the table of reasons.
+pub struct BinaryWriter<W: Write + Seek> {
+    writer: W,
+    records: u64,
+    checksum: Digest,
+    reasons: HashMap<String, u32>,
+}
+
+impl<W: Write + Seek> BinaryWriter<W> {
+    /// Create a writer, leaving room for the header at the start of the stream.
+    pub fn new(mut writer: W) -> Result<BinaryWriter<W>> {
+        writer.write_all(&[0; HEADER_SIZE])?;
+        Ok(BinaryWriter {
+            writer,
+            records: 0,
+            checksum: Digest::new(),
+            reasons: HashMap::new(),
+        })
+    }
+
+    /// Appends a transaction to the log.
+    pub fn write(&mut self, tx: &Transaction) -> Result<()> {
+        let mut record = encode(tx);
+        if let Some(reason) = tx.reason().filter(|reason| reason.len() > MAX_REASON) {
+            let next = self.reasons.len() as u32;
+            let index = *self.reasons.entry(reason.clone()).or_insert(next);
+            record[1] = LONG_REASON;
+            record[19..23].copy_from_slice(&index.to_le_bytes());
+        }
+        self.checksum.update_bytes(&record);
+        self.writer.write_all(&record)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Writes the table of reasons and the header, and returns the underlying stream.
+    pub fn finish(mut self) -> Result<W> {
+        let mut reasons: Vec<(String, u32)> = self.reasons.drain().collect();
+        reasons.sort_by_key(|(_, index)| *index);
+        for (reason, _) in &reasons {
+            let length = (reason.len() as u32).to_le_bytes();
+            for bytes in [&length[..], reason.as_bytes()] {
+                self.checksum.update_bytes(bytes);
+                self.writer.write_all(bytes)?;
+            }
+        }
+
+        let mut header = [0; HEADER_SIZE];
+        header[0..8].copy_from_slice(&MAGIC);
+        header[8..10].copy_from_slice(&VERSION.to_le_bytes());
+        header[10..12].copy_from_slice(&(RECORD_SIZE as u16).to_le_bytes());
+        header[12..16].copy_from_slice(&(reasons.len() as u32).to_le_bytes());
+        header[16..24].copy_from_slice(&self.records.to_le_bytes());
+        header[24..32].copy_from_slice(&self.checksum.value().to_le_bytes());
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Load transactions from a binary log written by `BinaryWriter`.
+///
+/// The header is checked before decoding any record, and the checksum once all of them have been read, so a
+/// log that has been truncated or corrupted is rejected as a whole.
+pub fn load_binary_transactions(mut reader: impl Read) -> Result<Vec<Transaction>> {
+    let mut header = [0; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    if !is_binary_log(&header) {
+        return Err(anyhow!("binary log not valid, wrong magic bytes."));
+    }
+    let version = u16::from_le_bytes([header[8], header[9]]);
+    if version == 0 || version > VERSION {
+        return Err(anyhow!(
+            "binary log not valid, unsupported version {}.",
+            version
+        ));
+    }
+    let record_size = u16::from_le_bytes([header[10], header[11]]) as usize;
+    if record_size != RECORD_SIZE {
+        return Err(anyhow!(
+            "binary log not valid, unexpected record size {}.",
+            record_size
+        ));
+    }
+    let long_reasons = u32::from_le_bytes(header[12..16].try_into()?);
+    let records = u64::from_le_bytes(header[16..24].try_into()?);
+    let checksum = u64::from_le_bytes(header[24..32].try_into()?);
+
+    let mut reader = io::BufReader::new(reader);
+    let mut digest = Digest::new();
+    let mut record = [0; RECORD_SIZE];
+    let mut res = Vec::with_capacity(records.min(1 << 20) as usize);
+    // transactions whose reason is in the table of reasons, along with its index
+    let mut pending = Vec::new();
+    for index in 0..records {
+        reader.read_exact(&mut record).map_err(|err| {
+            anyhow!(
+                "binary log not valid, failed reading record {} of {}: {}.",
+                index + 1,
+                records,
+                err
+            )
+        })?;
+        digest.update_bytes(&record);
+        if record[1] == LONG_REASON {
+            let reason = u32::from_le_bytes(record[19..23].try_into()?);
+            pending.push((res.len(), reason));
+        }
+        res.push(decode(&record, index)?);
+    }
+
+    let mut reasons = Vec::with_capacity(long_reasons.min(1 << 16) as usize);
+    for index in 0..long_reasons {
+        let mut length = [0; 4];
+        let mut read = |bytes: &mut [u8]| {
+            reader.read_exact(bytes).map_err(|err| {
+                anyhow!(
+                    "binary log not valid, failed reading reason {} of {}: {}.",
+                    index + 1,
+                    long_reasons,
+                    err
+                )
+            })
+        };
+        read(&mut length)?;
+        let mut reason = vec![0; u32::from_le_bytes(length) as usize];
+        read(&mut reason)?;
+        digest.update_bytes(&length);
+        digest.update_bytes(&reason);
+        reasons.push(String::from_utf8(reason)?);
+    }
+    if digest.value() != checksum {
+        return Err(anyhow!("binary log not valid, checksum mismatch."));
+    }
+    for (position, index) in pending {
+        let reason = reasons.get(index as usize).cloned().ok_or_else(|| {
+            anyhow!(
+                "binary log not valid, record {} refers to unknown reason {}.",
+                position + 1,
+                index
+            )
+        })?;
+        if let Transaction::Dispute(.., slot)
+        | Transaction::Resolve(.., slot)
+        | Transaction::Chargeback(.., slot) = &mut res[position]
+        {
+            *slot = Some(reason);
+        }
+    }
+    Ok(res)
+}
+
+/// Encodes a transaction into a record, along with its reason when it fits within the record.
+fn encode(tx: &Transaction) -> [u8; RECORD_SIZE] {
+    let (kind, amount, currency) = match tx {
+        Transaction::Deposit(_, _, amount, currency) => (0, amount, Some(currency)),
+        Transaction::Withdrawal(_, _, amount, currency) => (1, amount, Some(currency)),
+        Transaction::Dispute(_, _, amount, _) => (2, amount, None),
+        Transaction::Resolve(_, _, amount, _) => (3, amount, None),
+        Transaction::Chargeback(_, _, amount, _) => (4, amount, None),
+        Transaction::Authorize(_, _, amount, currency) => (5, amount, Some(currency)),
+        Transaction::Capture(_, _, amount) => (6, amount, None),
+        Transaction::Release(_, _, amount) => (7, amount, None),
+        Transaction::Refund(_, _, amount) => (8, amount, None),
+        Transaction::Interest(_, _, amount, currency) => (9, amount, Some(currency)),
+        Transaction::Representment(_, _, amount) => (10, amount, None),
+    };
+
+    let mut record = [0; RECORD_SIZE];
+    record[0] = kind;
+    record[1] = NO_REASON;
+    record[2..4].copy_from_slice(&tx.client_id().to_le_bytes());
+    record[4..8].copy_from_slice(&tx.id().to_le_bytes());
+    record[8..16].copy_from_slice(&amount.to_le_bytes());
+    if let Some(currency) = currency {
+        record[16..19].copy_from_slice(currency.code().as_bytes());
+    }
+    if let Some(reason) = tx.reason().filter(|reason| reason.len() <= MAX_REASON) {
+        record[1] = reason.len() as u8;
+        record[19..19 + reason.len()].copy_from_slice(reason.as_bytes());
+    }
+    record
+}
+
+fn decode(record: &[u8; RECORD_SIZE], index: u64) -> Result<Transaction> {
+    let client = u16::from_le_bytes([record[2], record[3]]);
+    let tx = u32::from_le_bytes(record[4..8].try_into()?);
+    let amount = f64::from_le_bytes(record[8..16].try_into()?);
+    let currency = || -> Result<Currency> { std::str::from_utf8(&record[16..19])?.parse() };
+    let reason = match record[1] {
+        NO_REASON => None,
+        // filled in once the table of reasons has been read
+        LONG_REASON => Some(String::new()),
+        len if len as usize <= MAX_REASON => {
+            Some(std::str::from_utf8(&record[19..19 + len as usize])?.to_string())
+        }
+        len => {
+            return Err(anyhow!(
+                "binary log not valid, record {} has a reason of {} bytes.",
+                index + 1,
+                len
+            ))
+        }
+    };
+
+    Ok(match record[0] {
+        0 => Transaction::Deposit(client, tx, amount, currency()?),
+        1 => Transaction::Withdrawal(client, tx, amount, currency()?),
+        2 => Transaction::Dispute(client, tx, amount, reason),
+        3 => Transaction::Resolve(client, tx, amount, reason),
+        4 => Transaction::Chargeback(client, tx, amount, reason),
+        5 => Transaction::Authorize(client, tx, amount, currency()?),
+        6 => Transaction::Capture(client, tx, amount),
+        7 => Transaction::Release(client, tx, amount),
+        8 => Transaction::Refund(client, tx, amount),
+        9 => Transaction::Interest(client, tx, amount, currency()?),
+        10 => Transaction::Representment(client, tx, amount),
+        kind => {
+            return Err(anyhow!(
+                "binary log not valid, record {} has unknown type {}.",
+                index + 1,
+                kind
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn transactions() -> Vec<Transaction> {
+        vec![
+            Transaction::Deposit(1, 1, 10.5, Currency::GBP),
+            Transaction::Withdrawal(1, 2, 0.25, Currency::EUR),
+            Transaction::Dispute(1, 1, 0.0, Some("fraud".to_string())),
+            Transaction::Chargeback(1, 1, 0.0, None),
+            Transaction::Representment(1, 1, 0.0),
+            Transaction::Interest(2, 3, 0.0001, Currency::USD),
+        ]
+    }
+
+    fn encoded() -> Vec<u8> {
+        let mut writer = BinaryWriter::new(Cursor::new(Vec::new())).unwrap();
+        for tx in transactions() {
+            writer.write(&tx).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_binary_log_roundtrip() {
+        let bytes = encoded();
+        assert_eq!(bytes.len(), HEADER_SIZE + 6 * RECORD_SIZE);
+        assert!(is_binary_log(&bytes));
+        assert_eq!(
+            load_binary_transactions(bytes.as_slice()).unwrap(),
+            transactions()
+        );
+    }
+
+    #[test]
+    fn test_corrupted_binary_log_is_rejected() {
+        let mut bytes = encoded();
+        bytes[HEADER_SIZE + 8] ^= 0x01;
+        assert_eq!(
+            load_binary_transactions(bytes.as_slice())
+                .err()
+                .unwrap()
+                .to_string(),
+            "binary log not valid, checksum mismatch."
+        );
+
+        let mut bytes = encoded();
+        bytes[8] = 3;
+        assert_eq!(
+            load_binary_transactions(bytes.as_slice())
+                .err()
+                .unwrap()
+                .to_string(),
+            "binary log not valid, unsupported version 3."
+        );
+        assert!(load_binary_transactions(&encoded()[..HEADER_SIZE + 10]).is_err());
+    }
+
+    #[test]
+    fn test_long_reasons_are_stored_in_the_table_of_reasons() {
+        let long = "r".repeat(MAX_REASON + 1);
+        let transactions = vec![
+            Transaction::Dispute(1, 1, 0.0, Some(long.clone())),
+            Transaction::Chargeback(1, 1, 0.0, Some("fraud".to_string())),
+            Transaction::Dispute(2, 2, 0.0, Some("a reason of a few more bytes".to_string())),
+            Transaction::Resolve(1, 1, 0.0, Some(long)),
+        ];
+        let mut writer = BinaryWriter::new(Cursor::new(Vec::new())).unwrap();
+        for tx in &transactions {
+            writer.write(tx).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        // the reason repeated is stored once
+        assert_eq!(
+            bytes.len(),
+            HEADER_SIZE + 4 * RECORD_SIZE + 4 + (MAX_REASON + 1) + 4 + 28
+        );
+        assert_eq!(
+            load_binary_transactions(bytes.as_slice()).unwrap(),
+            transactions
+        );
+
+        let mut truncated = bytes.clone();
+        truncated.truncate(bytes.len() - 1);
+        assert!(load_binary_transactions(truncated.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+/*
+ The binlog module provides a compact binary encoding of transactions, used for replaying them faster than CSV.
+*/
+mod binlog;
+
+pub use binlog::{is_binary_log, load_binary_transactions, BinaryWriter, BINARY_MAGIC_LEN};
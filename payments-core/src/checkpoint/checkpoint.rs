@@ -0,0 +1,226 @@
+use crate::account::Transaction;
+use crate::ledger::Ledger;
+use crate::limits::Withdrawals;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A Digest fingerprints a sequence of transactions, so an input can be checked to start with the
+/// transactions that have already been processed.
+///
+/// It's a 64 bits FNV-1a hash over the JSON encoding of every transaction, which is stable across
+/// runs and builds.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Digest(u64);
+
+impl Default for Digest {
+    fn default() -> Digest {
+        Digest(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Digest {
+    /// Create the digest of an empty sequence of transactions.
+    pub fn new() -> Digest {
+        Digest::default()
+    }
+
+    /// Adds a transaction to the digested sequence.
+    pub fn update(&mut self, tx: &Transaction) {
+        let bytes = serde_json::to_vec(tx).expect("transactions are always serializable");
+        self.update_bytes(&bytes);
+        self.update_bytes(b"\n");
+    }
+
+    /// Adds raw bytes to the digest.
+    pub fn update_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Returns the value of the digest.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A Checkpoint records how far an input has been processed along with the state of the engine at that point.
+///
+/// The input is identified by its name, and the processed prefix by the number of `records` and their `Digest`.
+/// Restoring a checkpoint and processing the same input again only applies the records after the checkpoint.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint {
+    /// Name of the input the checkpoint was taken from.
+    pub input: String,
+    /// Number of records of the input that have been processed, either applied or rejected.
+    pub records: u64,
+    /// Number of the processed records that were rejected.
+    #[serde(default)]
+    pub rejected: u64,
+    /// Bytes of the rejections written when the checkpoint was taken, if they were being written, so the ones
+    /// written after it can be discarded when resuming from it.
+    #[serde(default)]
+    pub rejections: Option<u64>,
+    /// Digest of the records that have been processed.
+    pub digest: Digest,
+    /// Accounts after processing the records.
+    pub ledger: Ledger,
+    /// Withdrawals counting towards the limits after processing the records.
+    pub withdrawals: Withdrawals,
+}
+
+impl Checkpoint {
+    /// Load a checkpoint from a stream of bytes in JSON format.
+    pub fn from_reader(reader: impl io::Read) -> Result<Checkpoint> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Writes the checkpoint in JSON format.
+    pub fn to_writer(&self, writer: impl io::Write) -> Result<()> {
+        Ok(serde_json::to_writer(writer, self)?)
+    }
+
+    /// Writes the checkpoint to a file, replacing it only once it has been fully written so a crash
+    /// never leaves a partial checkpoint behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let mut writer = io::BufWriter::new(fs::File::create(&tmp)?);
+        self.to_writer(&mut writer)?;
+        io::Write::flush(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Verifies that `transactions`, read from `input`, start with the records that have been processed.
+    ///
+    /// It returns the number of records to skip for resuming after the checkpoint.
+    pub fn verify(&self, input: &str, transactions: &[Transaction]) -> Result<usize> {
+        let mut verifier = self.verifier(input)?;
+        for tx in transactions {
+            if !verifier.skip(tx)? {
+                break;
+            }
+        }
+        verifier.finish()?;
+        Ok(verifier.skipped() as usize)
+    }
+
+    /// Creates a verifier for the records read from `input`, failing if the checkpoint was taken from another one.
+    pub fn verifier(&self, input: &str) -> Result<Verifier> {
+        if input != self.input {
+            return Err(anyhow!(
+                "checkpoint not valid, it was taken from input '{}'.",
+                self.input
+            ));
+        }
+        Ok(Verifier {
+            records: self.records,
+            digest: self.digest,
+            skipped: 0,
+            read: Digest::new(),
+        })
+    }
+}
+
+/// Verifies the records of an input as they are read, skipping the ones processed before a checkpoint, so that
+/// resuming doesn't need to hold the input in memory.
+#[derive(Debug)]
+pub struct Verifier {
+    records: u64,
+    digest: Digest,
+    skipped: u64,
+    read: Digest,
+}
+
+impl Verifier {
+    /// Tells whether a transaction, read in order from the input, was processed before the checkpoint and must be
+    /// skipped. It fails once the records processed have been read if they have changed.
+    pub fn skip(&mut self, tx: &Transaction) -> Result<bool> {
+        if self.skipped == self.records {
+            return Ok(false);
+        }
+        self.read.update(tx);
+        self.skipped += 1;
+        if self.skipped == self.records && self.read != self.digest {
+            return Err(anyhow!(
+                "checkpoint not valid, the first {} records of the input have changed.",
+                self.records
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Fails if the input ended before the records processed have been read.
+    pub fn finish(&self) -> Result<()> {
+        if self.skipped < self.records {
+            return Err(anyhow!(
+                "checkpoint not valid, the input has {} records but {} were processed.",
+                self.skipped,
+                self.records
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of records skipped so far.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+
+    fn checkpoint(transactions: &[Transaction]) -> Checkpoint {
+        let mut digest = Digest::new();
+        transactions.iter().for_each(|tx| digest.update(tx));
+        Checkpoint {
+            input: "input.csv".to_string(),
+            records: transactions.len() as u64,
+            rejected: 0,
+            rejections: None,
+            digest,
+            ledger: Ledger::new(),
+            withdrawals: Withdrawals::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_changed_input() {
+        let transactions = vec![
+            Transaction::Deposit(1, 1, 1.0, Currency::EUR),
+            Transaction::Deposit(1, 2, 2.0, Currency::EUR),
+        ];
+        let checkpoint = checkpoint(&transactions[..1]);
+        assert_eq!(checkpoint.verify("input.csv", &transactions).unwrap(), 1);
+
+        assert_eq!(
+            checkpoint
+                .verify("other.csv", &transactions)
+                .err()
+                .unwrap()
+                .to_string(),
+            "checkpoint not valid, it was taken from input 'input.csv'."
+        );
+        assert_eq!(
+            checkpoint
+                .verify("input.csv", &transactions[1..])
+                .err()
+                .unwrap()
+                .to_string(),
+            "checkpoint not valid, the first 1 records of the input have changed."
+        );
+        assert!(checkpoint.verify("input.csv", &[]).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+/*
+ The checkpoint module keeps track of the processed input, so processing can resume after a crash.
+*/
+mod checkpoint;
+
+pub use checkpoint::{Checkpoint, Digest, Verifier};
@@ -0,0 +1,86 @@
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A Currency is identified by its three letters ISO 4217 code, e.g. `EUR`, `GBP` or `USD`.
+///
+/// Transactions that don't state any currency use the default one, `EUR`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const EUR: Currency = Currency(*b"EUR");
+    pub const GBP: Currency = Currency(*b"GBP");
+    pub const USD: Currency = Currency(*b"USD");
+
+    /// Returns the currency code.
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).expect("currency codes are ascii")
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Currency {
+        Currency::EUR
+    }
+}
+
+impl FromStr for Currency {
+    type Err = Error;
+
+    /// Parses a currency code, letters are not case sensitive.
+    fn from_str(code: &str) -> Result<Currency> {
+        let code = code.trim().to_ascii_uppercase();
+        match code.as_bytes() {
+            [a, b, c] if code.bytes().all(|letter| letter.is_ascii_uppercase()) => {
+                Ok(Currency([*a, *b, *c]))
+            }
+            _ => Err(anyhow!("Not a valid currency code '{}'", code)),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Currency, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_currency_codes() {
+        assert_eq!("GBP".parse::<Currency>().unwrap(), Currency::GBP);
+        assert_eq!(" usd ".parse::<Currency>().unwrap(), Currency::USD);
+        assert_eq!(Currency::default().to_string(), "EUR");
+        assert_eq!(
+            "EURO".parse::<Currency>().err().unwrap().to_string(),
+            "Not a valid currency code 'EURO'"
+        );
+        assert!("E1R".parse::<Currency>().is_err());
+    }
+}
@@ -0,0 +1,6 @@
+/*
+ The currency module provides the currency codes the accounts' balances are kept in.
+*/
+mod currency;
+
+pub use currency::Currency;
@@ -0,0 +1,483 @@
+use super::engine::Engine;
+use crate::account::{Account, Amount, ClientId, Currency, Timestamp, Transaction};
+use crate::ledger::Ledger;
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+/// Number of messages that can be queued for the router or for an actor before senders wait.
+const MAILBOX: usize = 64;
+
+/// Number of transactions of a client the router queues while its mailbox is full. Beyond it the router waits for
+/// the actor to catch up, so that callers feel the backpressure of a busy client.
+const OVERFLOW: usize = 64;
+
+/// The state of a client kept in an `AccountStore` while its actor is evicted: its account and the withdrawals
+/// evaluated by the limits.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientState {
+    pub account: Account,
+    pub withdrawals: VecDeque<(Timestamp, Amount, Currency)>,
+}
+
+/// An AccountStore keeps the state of the clients whose actors have been evicted, e.g. in memory or in a database.
+pub trait AccountStore: Send + Sync + 'static {
+    /// Returns the state of a client, if it has been stored.
+    fn load(&self, client_id: ClientId) -> Option<ClientState>;
+
+    /// Stores the state of a client, replacing the previous one.
+    fn store(&self, client_id: ClientId, state: ClientState);
+}
+
+/// Keeps the state of the clients in memory.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    clients: Mutex<HashMap<ClientId, ClientState>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Returns a ledger with the accounts of every client stored.
+    pub fn ledger(&self) -> Ledger {
+        let clients = self.clients.lock().expect("store lock poisoned");
+        clients
+            .iter()
+            .map(|(client_id, state)| (*client_id, state.account.clone()))
+            .collect()
+    }
+}
+
+impl AccountStore for MemoryStore {
+    fn load(&self, client_id: ClientId) -> Option<ClientState> {
+        let clients = self.clients.lock().expect("store lock poisoned");
+        clients.get(&client_id).cloned()
+    }
+
+    fn store(&self, client_id: ClientId, state: ClientState) {
+        let mut clients = self.clients.lock().expect("store lock poisoned");
+        clients.insert(client_id, state);
+    }
+}
+
+type Reply = oneshot::Sender<Result<()>>;
+
+type Delivery = (Transaction, Reply);
+
+/// Resolves once the mailbox of a client has room, or has been closed.
+type Room = BoxFuture<'static, (ClientId, Option<mpsc::OwnedPermit<Delivery>>)>;
+
+enum Message {
+    Process(Transaction, Reply),
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// The actor of an active client: its mailbox, the transactions waiting for room in it, its task and when it last
+/// received a transaction.
+struct Actor {
+    mailbox: mpsc::Sender<Delivery>,
+    /// Transactions routed while the mailbox was full, delivered in order as soon as it has room.
+    overflow: VecDeque<Delivery>,
+    task: JoinHandle<()>,
+    seen: Instant,
+}
+
+/// The ActorEngine applies transactions on tokio tasks, one per active client, each one owning the client's
+/// account and receiving its transactions through a mailbox.
+///
+/// A router task dispatches every transaction to the actor of its client, starting it with the state found in
+/// the `AccountStore` when the client isn't active. Actors that haven't received transactions for the idle
+/// timeout are evicted: they apply the transactions left in their mailbox and store the client's state. Every
+/// actor is a single client `Engine` sharing the limits, converter and policy of the engine the actor engine is
+/// created from, so transactions are applied in the same way and in the order they are given for every client.
+///
+/// The engine is a handle that can be cloned for processing transactions from several tasks.
+#[derive(Clone)]
+pub struct ActorEngine {
+    router: mpsc::Sender<Message>,
+}
+
+impl ActorEngine {
+    /// Starts the router of an engine on the current tokio runtime.
+    ///
+    /// The accounts already held by the engine are moved into the store along with their withdrawals, replacing
+    /// the states stored for the same clients.
+    pub fn spawn(
+        mut engine: Engine,
+        store: Arc<dyn AccountStore>,
+        idle_timeout: Duration,
+    ) -> ActorEngine {
+        for (client_id, state) in engine.take_client_states() {
+            store.store(client_id, state);
+        }
+        let (router, inbox) = mpsc::channel(MAILBOX);
+        let router_state = Router {
+            engine,
+            store,
+            idle_timeout,
+            actors: HashMap::new(),
+            evicting: HashMap::new(),
+            rooms: FuturesUnordered::new(),
+        };
+        tokio::spawn(router_state.run(inbox));
+        ActorEngine { router }
+    }
+
+    /// Processes a transaction, waiting for the actor of its client to apply it.
+    pub async fn process(&self, tx: Transaction) -> Result<()> {
+        let (reply, outcome) = oneshot::channel();
+        self.router
+            .send(Message::Process(tx, reply))
+            .await
+            .map_err(|_| anyhow!("processing failed, the actor engine has stopped."))?;
+        outcome
+            .await
+            .map_err(|_| anyhow!("processing failed, the actor of the client has stopped."))?
+    }
+
+    /// Stops every actor once the transactions given so far have been applied, storing the state of all clients.
+    pub async fn shutdown(self) -> Result<()> {
+        let (done, stopped) = oneshot::channel();
+        self.router
+            .send(Message::Shutdown(done))
+            .await
+            .map_err(|_| anyhow!("shutdown failed, the actor engine has stopped."))?;
+        stopped
+            .await
+            .map_err(|_| anyhow!("shutdown failed, the actor engine has stopped."))
+    }
+}
+
+struct Router {
+    engine: Engine,
+    store: Arc<dyn AccountStore>,
+    idle_timeout: Duration,
+    actors: HashMap<ClientId, Actor>,
+    /// Tasks of the evicted actors that may still be storing the state of their clients.
+    evicting: HashMap<ClientId, JoinHandle<()>>,
+    /// Waits for room in the mailboxes of the actors with an overflow, so that a busy client doesn't hold up the
+    /// transactions of the others.
+    rooms: FuturesUnordered<Room>,
+}
+
+impl Router {
+    async fn run(mut self, mut inbox: mpsc::Receiver<Message>) {
+        let mut ticks = time::interval((self.idle_timeout / 2).max(Duration::from_millis(1)));
+        loop {
+            tokio::select! {
+                message = inbox.recv() => match message {
+                    Some(Message::Process(tx, reply)) => self.route(tx, reply).await,
+                    Some(Message::Shutdown(done)) => {
+                        self.stop().await;
+                        let _ = done.send(());
+                        return;
+                    }
+                    None => {
+                        self.stop().await;
+                        return;
+                    }
+                },
+                Some((client_id, permit)) = self.rooms.next(), if !self.rooms.is_empty() => {
+                    self.make_room(client_id, permit)
+                }
+                _ = ticks.tick() => self.evict_idle(),
+            }
+        }
+    }
+
+    async fn route(&mut self, tx: Transaction, reply: Reply) {
+        let client_id = tx.client_id();
+        if !self.actors.contains_key(&client_id) {
+            // the client's state must be stored before starting it again
+            if let Some(task) = self.evicting.remove(&client_id) {
+                let _ = task.await;
+            }
+            let state = self.store.load(client_id);
+            let engine = self.engine.for_client(client_id, state);
+            let (mailbox, messages) = mpsc::channel(MAILBOX);
+            let task = tokio::spawn(run_actor(client_id, engine, messages, self.store.clone()));
+            let seen = Instant::now();
+            let actor = Actor {
+                mailbox,
+                overflow: VecDeque::new(),
+                task,
+                seen,
+            };
+            self.actors.insert(client_id, actor);
+        }
+        if let Some(actor) = self.actors.get_mut(&client_id) {
+            actor.seen = Instant::now();
+            if actor.overflow.len() >= OVERFLOW {
+                // makes room in the overflow by handing its oldest transaction over to the actor
+                match actor.mailbox.reserve().await {
+                    Ok(permit) => {
+                        if let Some(delivery) = actor.overflow.pop_front() {
+                            permit.send(delivery);
+                        }
+                    }
+                    Err(_) => return stopped(client_id, reply),
+                }
+            }
+            actor.overflow.push_back((tx, reply));
+            // an actor with an earlier overflow is already waiting for room
+            if actor.overflow.len() == 1 {
+                self.deliver(client_id);
+            }
+        }
+    }
+
+    /// Delivers the overflow of a client to its actor until the mailbox is full, then waits for room in it without
+    /// blocking the router.
+    fn deliver(&mut self, client_id: ClientId) {
+        let actor = match self.actors.get_mut(&client_id) {
+            Some(actor) => actor,
+            None => return,
+        };
+        while let Some(delivery) = actor.overflow.pop_front() {
+            match actor.mailbox.try_send(delivery) {
+                Ok(()) => (),
+                Err(mpsc::error::TrySendError::Full(delivery)) => {
+                    actor.overflow.push_front(delivery);
+                    let mailbox = actor.mailbox.clone();
+                    self.rooms.push(Box::pin(async move {
+                        (client_id, mailbox.reserve_owned().await.ok())
+                    }));
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed((_, reply))) => stopped(client_id, reply),
+            }
+        }
+    }
+
+    /// Hands the next transaction of a client's overflow to its actor once the mailbox has room.
+    fn make_room(&mut self, client_id: ClientId, permit: Option<mpsc::OwnedPermit<Delivery>>) {
+        let actor = match self.actors.get_mut(&client_id) {
+            Some(actor) => actor,
+            None => return,
+        };
+        if let (Some(permit), Some(delivery)) = (permit, actor.overflow.pop_front()) {
+            permit.send(delivery);
+        }
+        self.deliver(client_id);
+    }
+
+    fn evict_idle(&mut self) {
+        let idle: Vec<ClientId> = self
+            .actors
+            .iter()
+            .filter(|(_, actor)| actor.overflow.is_empty())
+            .filter(|(_, actor)| actor.seen.elapsed() >= self.idle_timeout)
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in idle {
+            if let Some(actor) = self.actors.remove(&client_id) {
+                // dropping the mailbox lets the actor finish once it has applied the queued transactions
+                self.evicting.insert(client_id, actor.task);
+            }
+        }
+        self.evicting.retain(|_, task| !task.is_finished());
+    }
+
+    async fn stop(&mut self) {
+        // the waits for room hold on to the mailboxes, the overflows are delivered directly instead
+        self.rooms.clear();
+        for (client_id, actor) in self.actors.iter_mut() {
+            for delivery in actor.overflow.drain(..) {
+                if let Err(mpsc::error::SendError((_, reply))) = actor.mailbox.send(delivery).await
+                {
+                    stopped(*client_id, reply);
+                }
+            }
+        }
+        let tasks = self
+            .actors
+            .drain()
+            .map(|(_, actor)| actor.task)
+            .chain(self.evicting.drain().map(|(_, task)| task))
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let _ = task.await;
+        }
+    }
+}
+
+/// Replies to a transaction that couldn't be handed to the stopped actor of its client.
+fn stopped(client_id: ClientId, reply: Reply) {
+    let _ = reply.send(Err(anyhow!(
+        "processing failed, the actor of client {} has stopped.",
+        client_id
+    )));
+}
+
+async fn run_actor(
+    client_id: ClientId,
+    mut engine: Engine,
+    mut messages: mpsc::Receiver<Delivery>,
+    store: Arc<dyn AccountStore>,
+) {
+    while let Some((tx, reply)) = messages.recv().await {
+        let _ = reply.send(engine.process(tx));
+    }
+    if let Some(state) = engine.into_client_state(client_id) {
+        store.store(client_id, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+    use crate::ledger::write_ledger;
+    use crate::limits::{Limits, TierLimits};
+
+    fn limits() -> Limits {
+        let mut limits = Limits::new();
+        limits.set_tier(
+            "standard",
+            TierLimits {
+                daily: Some(3.0),
+                ..TierLimits::default()
+            },
+        );
+        limits.set_default_tier("standard");
+        limits
+    }
+
+    /// Advances the paused clock, letting the router and the actors run their timers.
+    async fn idle(duration: Duration) {
+        time::advance(duration).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_actor_engine_matches_engine() {
+        let transactions: Vec<Transaction> = (1..=300u32)
+            .map(|tx| match tx % 3 {
+                0 => Transaction::Withdrawal((tx % 7) as u16, tx, 1.0, Currency::EUR),
+                _ => Transaction::Deposit((tx % 7) as u16, tx, 1.5, Currency::EUR),
+            })
+            .collect();
+        let mut engine = Engine::new().with_limits(limits());
+        let expected: Vec<bool> = transactions
+            .iter()
+            .map(|tx| engine.process(tx.clone()).is_ok())
+            .collect();
+
+        let store = Arc::new(MemoryStore::new());
+        let actors = ActorEngine::spawn(
+            Engine::new().with_limits(limits()),
+            store.clone(),
+            Duration::from_millis(5),
+        );
+        let mut outcomes = Vec::new();
+        for (index, tx) in transactions.into_iter().enumerate() {
+            if index % 50 == 0 {
+                // lets the idle actors be evicted and started again from the store
+                idle(Duration::from_millis(20)).await;
+            }
+            outcomes.push(actors.process(tx).await.is_ok());
+        }
+        idle(Duration::from_millis(20)).await;
+        assert_eq!(store.ledger().len(), 7);
+        actors.shutdown().await.unwrap();
+
+        assert_eq!(outcomes, expected);
+        let (mut output, mut expected) = (Vec::new(), Vec::new());
+        write_ledger(&mut output, &store.ledger()).unwrap();
+        write_ledger(&mut expected, engine.ledger()).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_router_queues_transactions_beyond_full_mailbox() {
+        let store = Arc::new(MemoryStore::new());
+        let mut router = Router {
+            engine: Engine::new(),
+            store: store.clone(),
+            idle_timeout: Duration::from_secs(60),
+            actors: HashMap::new(),
+            evicting: HashMap::new(),
+            rooms: FuturesUnordered::new(),
+        };
+        let deposit = |tx| Transaction::Deposit(1, tx, 1.0, Currency::EUR);
+        let mut outcomes = Vec::new();
+        for tx in 1..=(MAILBOX + OVERFLOW) as u32 {
+            let (reply, outcome) = oneshot::channel();
+            router.route(deposit(tx), reply).await;
+            outcomes.push(outcome);
+        }
+        assert_eq!(router.actors[&1].overflow.len(), OVERFLOW);
+
+        // the transactions of the other clients are still handed over while the first mailbox is full
+        let (reply, outcome) = oneshot::channel();
+        let tx = Transaction::Deposit(2, 1_000, 1.0, Currency::EUR);
+        router.route(tx, reply).await;
+        outcomes.push(outcome);
+        assert!(router.actors[&2].overflow.is_empty());
+
+        // beyond the cap the router waits for the busy actor instead of queueing more
+        for tx in (MAILBOX + OVERFLOW) as u32 + 1..=(MAILBOX + OVERFLOW) as u32 * 2 {
+            let (reply, outcome) = oneshot::channel();
+            router.route(deposit(tx), reply).await;
+            outcomes.push(outcome);
+            assert!(router.actors[&1].overflow.len() <= OVERFLOW);
+        }
+
+        // the overflow is delivered in order as the actor makes room
+        let (client_id, permit) = router.rooms.next().await.unwrap();
+        router.make_room(client_id, permit);
+        assert!(router.actors[&1].overflow.len() < OVERFLOW);
+        router.stop().await;
+        for outcome in outcomes {
+            outcome.await.unwrap().unwrap();
+        }
+        let mut engine = Engine::new();
+        for tx in 1..=(MAILBOX + OVERFLOW) as u32 * 2 {
+            engine.process(deposit(tx)).unwrap();
+        }
+        assert_eq!(
+            store.ledger()[&1].to_string(),
+            engine.ledger()[&1].to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_actor_engine_keeps_accounts_of_the_engine() {
+        let mut engine = Engine::new().with_limits(limits()).with_as_of(0);
+        engine
+            .process(Transaction::Deposit(1, 1, 10.0, Currency::EUR))
+            .unwrap();
+        engine
+            .process(Transaction::Withdrawal(1, 2, 2.0, Currency::EUR))
+            .unwrap();
+
+        let store = Arc::new(MemoryStore::new());
+        let actors = ActorEngine::spawn(engine, store.clone(), Duration::from_secs(60));
+        // the withdrawal made before spawning still counts towards the daily limit
+        assert!(actors
+            .process(Transaction::Withdrawal(1, 3, 2.0, Currency::EUR))
+            .await
+            .is_err());
+        actors
+            .process(Transaction::Withdrawal(1, 4, 1.0, Currency::EUR))
+            .await
+            .unwrap();
+        actors.shutdown().await.unwrap();
+        assert_eq!(store.ledger()[&1].to_string(), "EUR,7,0,0,7,false");
+    }
+}
@@ -0,0 +1,568 @@
+use super::actor::ClientState;
+use crate::account::{Account, Amount, ClientId, Currency, Policy, Timestamp, Transaction};
+use crate::checkpoint::{Checkpoint, Digest};
+use crate::fx::{Conversion, Fx};
+use crate::ledger::Ledger;
+use crate::limits::{Limits, Withdrawals};
+use crate::reader::source::{Provenance, TransactionSource};
+use crate::validation::validate;
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use log::debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The Engine applies transactions to the accounts stored in its Ledger.
+///
+/// Transactions are validated before anything else, so transactions with invalid amounts are rejected with a
+/// `ValidationError` without even creating the client's account.
+///
+/// Accounts are created the first time a client is seen. Before a withdrawal is handed over to the
+/// client's account the engine consults the `Limits`, so withdrawals breaching them are rejected
+/// without touching the account.
+///
+/// When the engine has an `Fx` converter, withdrawals in a currency the client doesn't hold are funded
+/// from the client's balance in another currency. The first currency, in alphabetical order, with a
+/// rate available and enough funds for the conversion is used.
+///
+/// The engine counts and digests every transaction it's given, applied or rejected, so a `Checkpoint` of the
+/// processed input can be taken at any time and restored later on for resuming the processing.
+///
+/// Limits and conversions are evaluated at the time a transaction is processed, unless the engine has been given
+/// a fixed time through `with_as_of`, so that replaying an input gives the same results whenever it's run.
+#[derive(Debug, Default, Clone)]
+pub struct Engine {
+    ledger: Ledger,
+    limits: Limits,
+    fx: Option<Fx>,
+    policy: Policy,
+    as_of: Option<Timestamp>,
+    processed: u64,
+    rejected: u64,
+    digest: Digest,
+}
+
+impl Engine {
+    /// Create an engine with an empty ledger and no limits.
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    /// Sets the withdrawal limits consulted by the engine.
+    pub fn with_limits(mut self, limits: Limits) -> Engine {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the converter used for funding withdrawals in currencies the client doesn't hold.
+    pub fn with_fx(mut self, fx: Fx) -> Engine {
+        self.fx = Some(fx);
+        self
+    }
+
+    /// Sets the policy followed by the accounts the engine creates.
+    pub fn with_policy(mut self, policy: Policy) -> Engine {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the time every transaction is processed at, instead of the current time.
+    pub fn with_as_of(mut self, as_of: Timestamp) -> Engine {
+        self.as_of = Some(as_of);
+        self
+    }
+
+    /// Restores the state of the engine from a checkpoint, keeping its limits, converter and policy.
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Engine {
+        self.ledger = checkpoint.ledger;
+        self.limits.restore_withdrawals(checkpoint.withdrawals);
+        self.processed = checkpoint.records;
+        self.rejected = checkpoint.rejected;
+        self.digest = checkpoint.digest;
+        self
+    }
+
+    /// Processes a transaction at the time set through `with_as_of`, or else at the current time.
+    pub fn process(&mut self, tx: Transaction) -> Result<()> {
+        let now = self.as_of.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        });
+        self.process_at(tx, now)
+    }
+
+    /// Processes a transaction that has been requested at `now`.
+    pub fn process_at(&mut self, tx: Transaction, now: Timestamp) -> Result<()> {
+        self.processed += 1;
+        self.digest.update(&tx);
+        let res = self.apply(tx, now);
+        if res.is_err() {
+            self.rejected += 1;
+        }
+        res
+    }
+
+    /// Applies a transaction to the client's account, once it has been counted and digested.
+    fn apply(&mut self, tx: Transaction, now: Timestamp) -> Result<()> {
+        validate(&tx)?;
+
+        let client_id = tx.client_id();
+        let policy = &self.policy;
+        let account = self
+            .ledger
+            .entry(client_id)
+            .or_insert_with(|| Account::with_policy(client_id, policy.clone()));
+        debug!("Account before: {}, tx: {:?}", account, tx);
+
+        if let Transaction::Withdrawal(_, _, amount, currency) = tx {
+            self.limits.check(client_id, amount, currency, now)?;
+        }
+
+        let conversion = match (&tx, &self.fx) {
+            (Transaction::Withdrawal(_, _, amount, currency), Some(fx))
+                if account.balance(*currency).total == 0.0 =>
+            {
+                Engine::fund_conversion(account, fx, *amount, *currency, now)
+            }
+            _ => None,
+        };
+        let res = match conversion {
+            Some(conversion) => account.process_converted(tx.clone(), conversion),
+            None => account.process(tx.clone()),
+        };
+        debug!("Account after: {}", account);
+        res?;
+
+        if let Transaction::Withdrawal(_, _, amount, currency) = tx {
+            self.limits.record(client_id, amount, currency, now);
+        }
+        Ok(())
+    }
+
+    /// Processes the transactions of a stream as they arrive, e.g. from `stream_csv_transactions`.
+    ///
+    /// Transactions that cannot be read or applied are handed over to `on_rejected` along with their error, and
+    /// processing goes on with the next ones. It returns the number of transactions applied once the stream ends.
+    pub async fn process_stream<S>(
+        &mut self,
+        transactions: S,
+        mut on_rejected: impl FnMut(Option<&Transaction>, anyhow::Error),
+    ) -> u64
+    where
+        S: Stream<Item = Result<Transaction>>,
+    {
+        futures::pin_mut!(transactions);
+        let mut applied = 0;
+        while let Some(tx) = transactions.next().await {
+            let res = match tx {
+                Ok(tx) => self.process(tx.clone()).map_err(|err| (Some(tx), err)),
+                Err(err) => Err((None, err)),
+            };
+            match res {
+                Ok(()) => applied += 1,
+                Err((tx, err)) => on_rejected(tx.as_ref(), err),
+            }
+        }
+        applied
+    }
+
+    /// Processes every transaction of a source, such as a `CsvSource`, until it's exhausted.
+    ///
+    /// Transactions that cannot be read or applied are handed over to `on_rejected` along with their provenance
+    /// and error, and processing goes on with the next ones unless it returns an error, which stops the run. It
+    /// returns the number of transactions applied.
+    pub fn run<S>(
+        &mut self,
+        source: &mut S,
+        mut on_rejected: impl FnMut(Option<&Transaction>, &Provenance, anyhow::Error) -> Result<()>,
+    ) -> Result<u64>
+    where
+        S: TransactionSource + ?Sized,
+    {
+        let mut applied = 0;
+        while let Some((tx, provenance)) = source.next_transaction() {
+            let res = match tx {
+                Ok(tx) => self.process(tx.clone()).map_err(|err| (Some(tx), err)),
+                Err(err) => Err((None, err)),
+            };
+            match res {
+                Ok(()) => applied += 1,
+                Err((tx, err)) => on_rejected(tx.as_ref(), &provenance, err)?,
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Finds a balance of the account in another currency that can fund a withdrawal.
+    fn fund_conversion(
+        account: &Account,
+        fx: &Fx,
+        amount: Amount,
+        currency: Currency,
+        now: Timestamp,
+    ) -> Option<Conversion> {
+        account
+            .balances()
+            .into_iter()
+            .filter(|(from, _)| *from != currency)
+            .find_map(|(from, balance)| {
+                fx.quote(from, currency, amount, now)
+                    .filter(|conversion| conversion.amount <= balance.available)
+            })
+    }
+
+    /// Returns the number of transactions processed, either applied or rejected.
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    /// Returns the number of transactions processed that were rejected.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Takes a checkpoint of the transactions processed so far from `input` and the resulting state.
+    pub fn checkpoint(&self, input: &str) -> Checkpoint {
+        Checkpoint {
+            input: input.to_string(),
+            records: self.processed,
+            rejected: self.rejected,
+            rejections: None,
+            digest: self.digest,
+            ledger: self.ledger.clone(),
+            withdrawals: self.limits.withdrawals().clone(),
+        }
+    }
+
+    /// Returns the ledger holding the accounts.
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Consumes the engine returning its ledger.
+    pub fn into_ledger(self) -> Ledger {
+        self.ledger
+    }
+
+    /// Creates an engine with the configuration of this one, its limits, converter, policy and time, but none of
+    /// its state.
+    fn configured(&self) -> Engine {
+        Engine {
+            limits: self.limits.configuration(),
+            fx: self.fx.clone(),
+            policy: self.policy.clone(),
+            as_of: self.as_of,
+            ..Engine::default()
+        }
+    }
+
+    /// Creates an engine sharing the configuration of this one that holds the state of a single client.
+    pub(super) fn for_client(&self, client_id: ClientId, state: Option<ClientState>) -> Engine {
+        let mut engine = self.configured();
+        if let Some(state) = state {
+            engine.ledger.insert(client_id, state.account);
+            let withdrawals = std::iter::once((client_id, state.withdrawals)).collect();
+            engine.limits.restore_withdrawals(withdrawals);
+        }
+        engine
+    }
+
+    /// Consumes the engine returning the state of a client, if it has an account.
+    pub(super) fn into_client_state(mut self, client_id: ClientId) -> Option<ClientState> {
+        let account = self.ledger.remove(&client_id)?;
+        let withdrawals = self
+            .limits
+            .withdrawals()
+            .get(&client_id)
+            .cloned()
+            .unwrap_or_default();
+        Some(ClientState {
+            account,
+            withdrawals,
+        })
+    }
+
+    /// Takes the state of every client out of the engine, leaving it with an empty ledger and no withdrawals.
+    pub(super) fn take_client_states(&mut self) -> Vec<(ClientId, ClientState)> {
+        let mut withdrawals = self.limits.take_withdrawals();
+        self.ledger
+            .drain()
+            .map(|(client_id, account)| {
+                let withdrawals = withdrawals.remove(&client_id).unwrap_or_default();
+                (
+                    client_id,
+                    ClientState {
+                        account,
+                        withdrawals,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Splits the state of the engine into `shards` engines sharing its configuration: the accounts and withdrawals
+    /// of every client go to the engine at `client_id % shards`. The engine keeps its counters and digest.
+    pub(super) fn split(&mut self, shards: usize) -> Vec<Engine> {
+        let mut engines: Vec<Engine> = (0..shards).map(|_| self.configured()).collect();
+        for (client_id, account) in self.ledger.drain() {
+            engines[client_id as usize % shards]
+                .ledger
+                .insert(client_id, account);
+        }
+        let mut withdrawals = vec![Withdrawals::new(); shards];
+        for (client_id, client_withdrawals) in self.limits.take_withdrawals() {
+            withdrawals[client_id as usize % shards].insert(client_id, client_withdrawals);
+        }
+        for (engine, withdrawals) in engines.iter_mut().zip(withdrawals) {
+            engine.limits.restore_withdrawals(withdrawals);
+        }
+        engines
+    }
+
+    /// Digests a transaction handed over to a shard of the engine, which applies and counts it.
+    pub(super) fn digest(&mut self, tx: &Transaction) {
+        self.digest.update(tx);
+    }
+
+    /// Merges back a shard split from the engine: its accounts, withdrawals and counters.
+    pub(super) fn merge(&mut self, mut shard: Engine) {
+        self.ledger.extend(shard.ledger);
+        let mut withdrawals = self.limits.take_withdrawals();
+        withdrawals.extend(shard.limits.take_withdrawals());
+        self.limits.restore_withdrawals(withdrawals);
+        self.processed += shard.processed;
+        self.rejected += shard.rejected;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+    use crate::fx::RateTable;
+    use crate::limits::{LimitBreach, TierLimits};
+    use crate::reader::source::CsvSource;
+    use crate::validation::ValidationError;
+
+    fn engine_with_daily_limit(limit: f64) -> Engine {
+        let mut limits = Limits::new();
+        limits.set_tier(
+            "standard",
+            TierLimits {
+                daily: Some(limit),
+                ..TierLimits::default()
+            },
+        );
+        limits.set_client_tier(1, "standard");
+        Engine::new().with_limits(limits)
+    }
+
+    #[test]
+    fn test_withdrawal_breaching_limits_is_rejected() {
+        let mut engine = engine_with_daily_limit(5.0);
+        engine
+            .process_at(Transaction::Deposit(1, 1, 10.0, Currency::EUR), 0)
+            .unwrap();
+        engine
+            .process_at(Transaction::Withdrawal(1, 2, 4.0, Currency::EUR), 0)
+            .unwrap();
+
+        let res = engine.process_at(Transaction::Withdrawal(1, 3, 2.0, Currency::EUR), 0);
+        assert_eq!(
+            res.err().unwrap().downcast::<LimitBreach>().unwrap(),
+            LimitBreach::Daily {
+                limit: 5.0,
+                withdrawn: 4.0,
+                amount: 2.0
+            }
+        );
+        assert_eq!(engine.ledger()[&1].to_string(), "EUR,6,0,0,6,false");
+    }
+
+    #[test]
+    fn test_failed_withdrawal_does_not_count_towards_limits() {
+        let mut engine = engine_with_daily_limit(5.0);
+        engine
+            .process_at(Transaction::Deposit(1, 1, 1.0, Currency::EUR), 0)
+            .unwrap();
+        assert!(engine
+            .process_at(Transaction::Withdrawal(1, 2, 4.0, Currency::EUR), 0)
+            .is_err()); // insufficient funds
+        engine
+            .process_at(Transaction::Deposit(1, 3, 9.0, Currency::EUR), 0)
+            .unwrap();
+        assert!(engine
+            .process_at(Transaction::Withdrawal(1, 4, 5.0, Currency::EUR), 0)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_limits_are_evaluated_as_of_a_fixed_time() {
+        const DAY: Timestamp = 86_400;
+        let mut engine = engine_with_daily_limit(5.0).with_as_of(DAY);
+        engine
+            .process_at(Transaction::Deposit(1, 1, 10.0, Currency::EUR), 0)
+            .unwrap();
+        engine
+            .process_at(Transaction::Withdrawal(1, 2, 4.0, Currency::EUR), 0)
+            .unwrap();
+        // a day after the first withdrawal, regardless of the current time
+        engine
+            .process(Transaction::Withdrawal(1, 3, 4.0, Currency::EUR))
+            .unwrap();
+        assert!(engine
+            .process(Transaction::Withdrawal(1, 4, 2.0, Currency::EUR))
+            .is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_in_currency_not_held_is_converted() {
+        let mut rates = RateTable::new();
+        rates.insert(Currency::EUR, Currency::USD, 2.0, 0);
+        rates.insert(Currency::GBP, Currency::USD, 1.0, 0);
+        let mut engine = Engine::new().with_fx(Fx::new(rates));
+        engine
+            .process_at(Transaction::Deposit(1, 1, 1.0, Currency::EUR), 0)
+            .unwrap();
+        engine
+            .process_at(Transaction::Deposit(1, 2, 3.0, Currency::GBP), 0)
+            .unwrap();
+
+        // EUR funds are not enough, so the withdrawal is funded from GBP
+        assert!(engine
+            .process_at(Transaction::Withdrawal(1, 3, 2.5, Currency::USD), 0)
+            .is_ok());
+        let account = &engine.ledger()[&1];
+        assert_eq!(
+            account.to_string(),
+            "EUR,1,0,0,1,false;GBP,0.5,0,0,0.5,false"
+        );
+        assert_eq!(account.conversion(3).unwrap().from, Currency::GBP);
+
+        // a later dispute is reversed in GBP at the original rate
+        engine
+            .process_at(Transaction::Dispute(1, 3, 0.0, None), 10)
+            .unwrap();
+        assert_eq!(
+            engine.ledger()[&1].to_string(),
+            "EUR,1,0,0,1,false;GBP,0.5,2.5,0,3,false"
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_without_conversion_rate() {
+        let mut engine = Engine::new().with_fx(Fx::new(RateTable::new()));
+        engine
+            .process_at(Transaction::Deposit(1, 1, 10.0, Currency::EUR), 0)
+            .unwrap();
+        let res = engine.process_at(Transaction::Withdrawal(1, 2, 1.0, Currency::USD), 0);
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "withdrawal failed, insuficcient funds."
+        );
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let transactions = vec![
+            Transaction::Deposit(1, 1, 10.0, Currency::EUR),
+            Transaction::Withdrawal(1, 2, 4.0, Currency::EUR),
+            Transaction::Withdrawal(1, 3, 20.0, Currency::EUR), // rejected
+            Transaction::Deposit(2, 4, 1.0, Currency::EUR),
+            Transaction::Withdrawal(1, 5, 2.0, Currency::EUR),
+        ];
+        let mut engine = engine_with_daily_limit(7.0);
+        for tx in &transactions[..3] {
+            let _ = engine.process_at(tx.clone(), 0);
+        }
+        let mut bytes = Vec::new();
+        engine
+            .checkpoint("input.csv")
+            .to_writer(&mut bytes)
+            .unwrap();
+
+        // the restored engine skips the processed records and keeps the withdrawals counting towards limits
+        let checkpoint = Checkpoint::from_reader(bytes.as_slice()).unwrap();
+        let skip = checkpoint.verify("input.csv", &transactions).unwrap();
+        let mut engine = engine_with_daily_limit(7.0).with_checkpoint(checkpoint);
+        for tx in &transactions[skip..] {
+            let _ = engine.process_at(tx.clone(), 0);
+        }
+
+        assert_eq!(skip, 3);
+        assert_eq!(engine.processed(), 5);
+        assert_eq!(engine.rejected(), 1);
+        assert_eq!(engine.ledger()[&1].to_string(), "EUR,4,0,0,4,false");
+        assert_eq!(engine.ledger()[&2].to_string(), "EUR,1,0,0,1,false");
+        assert!(engine
+            .process_at(Transaction::Withdrawal(1, 6, 2.0, Currency::EUR), 0)
+            .is_err()); // daily limit reached
+    }
+
+    #[test]
+    fn test_invalid_amounts_are_rejected_before_the_account() {
+        let mut engine = Engine::new();
+        let res = engine.process_at(Transaction::Deposit(1, 7, -50.0, Currency::EUR), 0);
+        assert_eq!(
+            res.err().unwrap().downcast::<ValidationError>().unwrap(),
+            ValidationError::NegativeAmount { amount: -50.0 }
+        );
+        assert!(engine.ledger().is_empty());
+    }
+
+    #[test]
+    fn test_run_source() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,2.0
+deposit,1,x,2.0
+withdrawal,1,2,5.0
+withdrawal,1,3,1.5"
+            .as_bytes();
+        let mut engine = Engine::new();
+        let mut rejected = Vec::new();
+        let mut source = CsvSource::new("input.csv", input);
+        let applied = engine
+            .run(&mut source, |tx, provenance, _| {
+                rejected.push((tx.map(|tx| tx.id()), provenance.position));
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(applied, 2);
+        assert_eq!(rejected, vec![(None, 3), (Some(2), 4)]);
+        assert_eq!(engine.ledger()[&1].to_string(), "EUR,0.5,0,0,0.5,false");
+
+        // the run stops at the first error returned by the callback
+        let mut engine = Engine::new();
+        let mut source = CsvSource::new("input.csv", input);
+        let res = engine.run(&mut source, |_, provenance, err| {
+            Err(err.context(format!("line {}", provenance.position)))
+        });
+        assert_eq!(res.err().unwrap().to_string(), "line 3");
+        assert_eq!(engine.processed(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_stream() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,2.0
+deposit,1,x,2.0
+withdrawal,1,2,5.0
+withdrawal,1,3,1.5"
+            .as_bytes();
+        let mut engine = Engine::new();
+        let mut rejected = Vec::new();
+        let applied = engine
+            .process_stream(crate::stream_csv_transactions(input), |tx, _| {
+                rejected.push(tx.map(|tx| tx.id()))
+            })
+            .await;
+
+        assert_eq!(applied, 2);
+        assert_eq!(rejected, vec![None, Some(2)]);
+        assert_eq!(engine.ledger()[&1].to_string(), "EUR,0.5,0,0,0.5,false");
+    }
+}
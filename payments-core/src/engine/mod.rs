@@ -0,0 +1,10 @@
+/*
+ The engine module drives the processing of transactions against the accounts of a ledger.
+*/
+mod actor;
+mod engine;
+mod sharded;
+
+pub use actor::{AccountStore, ActorEngine, ClientState, MemoryStore};
+pub use engine::Engine;
+pub use sharded::{Rejected, ShardedEngine};
@@ -0,0 +1,196 @@
+use super::engine::Engine;
+use crate::account::Transaction;
+
+use anyhow::{anyhow, Result};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Number of transactions that can be queued for every shard before `process` blocks.
+const CAPACITY: usize = 1024;
+
+/// A transaction that could not be applied by a `ShardedEngine`.
+#[derive(Debug)]
+pub struct Rejected {
+    /// Position of the transaction among the ones given to the engine, starting at 0.
+    pub index: u64,
+    pub tx: Transaction,
+    pub err: anyhow::Error,
+}
+
+/// A shard of the engine: the queue of its worker thread and the worker itself.
+type Shard = (
+    SyncSender<(u64, Transaction)>,
+    JoinHandle<(Engine, Vec<Rejected>)>,
+);
+
+/// The ShardedEngine applies transactions on several worker threads, each one owning the accounts of a shard
+/// of the clients.
+///
+/// Transactions are routed to the worker at `client_id % shards` through a bounded queue, so the transactions
+/// of every client are applied in the same order as they are given, and accounts being independent the
+/// resulting ledger is the same as applying all of them on a single `Engine`. Every worker starts from the
+/// configuration of the engine it's created from, its limits, converter, policy and time, along with the accounts
+/// and withdrawals of its clients, which are merged back into the engine once finished.
+pub struct ShardedEngine {
+    engine: Engine,
+    shards: Vec<Shard>,
+    next: u64,
+}
+
+impl ShardedEngine {
+    /// Create an engine with `shards` worker threads out of a configured engine.
+    pub fn new(mut engine: Engine, shards: usize) -> ShardedEngine {
+        let shards = engine
+            .split(shards.max(1))
+            .into_iter()
+            .map(|mut engine| {
+                let (sender, receiver) = mpsc::sync_channel::<(u64, Transaction)>(CAPACITY);
+                let worker = thread::spawn(move || {
+                    let mut rejected = Vec::new();
+                    for (index, tx) in receiver {
+                        if let Err(err) = engine.process(tx.clone()) {
+                            rejected.push(Rejected { index, tx, err });
+                        }
+                    }
+                    (engine, rejected)
+                });
+                (sender, worker)
+            })
+            .collect();
+        ShardedEngine {
+            engine,
+            shards,
+            next: 0,
+        }
+    }
+
+    /// Queues a transaction for the worker owning the client's account, waiting while its queue is full.
+    pub fn process(&mut self, tx: Transaction) -> Result<()> {
+        let (sender, _) = &self.shards[tx.client_id() as usize % self.shards.len()];
+        self.engine.digest(&tx);
+        sender
+            .send((self.next, tx))
+            .map_err(|_| anyhow!("processing failed, a worker of the sharded engine stopped."))?;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Waits for the workers to apply every queued transaction, and returns the engine with the state of every
+    /// shard merged back, so it can be checkpointed or go on processing, along with the rejected transactions in
+    /// the order they were given.
+    pub fn finish(self) -> Result<(Engine, Vec<Rejected>)> {
+        let mut engine = self.engine;
+        let mut rejected = Vec::new();
+        for (sender, worker) in self.shards {
+            drop(sender);
+            let (shard, shard_rejected) = worker.join().map_err(|_| {
+                anyhow!("processing failed, a worker of the sharded engine panicked.")
+            })?;
+            engine.merge(shard);
+            rejected.extend(shard_rejected);
+        }
+        rejected.sort_by_key(|rejected| rejected.index);
+        Ok((engine, rejected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{Currency, Policy};
+    use crate::ledger::write_ledger;
+    use crate::limits::{Limits, TierLimits};
+
+    fn transactions() -> Vec<Transaction> {
+        let mut transactions = Vec::new();
+        for tx in 1..=2000u32 {
+            let client = (tx % 37) as u16;
+            transactions.push(match tx % 6 {
+                0 => Transaction::Withdrawal(client, tx, 2.5, Currency::EUR),
+                1 => Transaction::Dispute(client, tx.saturating_sub(6), 0.0, None),
+                2 => Transaction::Chargeback(client, tx.saturating_sub(7), 0.0, None),
+                3 => Transaction::Deposit(client, tx, 1.0, Currency::GBP),
+                _ => Transaction::Deposit(client, tx, 1.5, Currency::EUR),
+            });
+        }
+        transactions
+    }
+
+    #[test]
+    fn test_sharded_engine_matches_sequential_engine() {
+        let mut engine = Engine::new().with_policy(Policy::default());
+        let mut rejected = Vec::new();
+        for (index, tx) in transactions().into_iter().enumerate() {
+            if engine.process(tx).is_err() {
+                rejected.push(index as u64);
+            }
+        }
+        assert!(!rejected.is_empty());
+        let mut expected = Vec::new();
+        write_ledger(&mut expected, engine.ledger()).unwrap();
+
+        for shards in [1, 4, 7] {
+            let mut sharded = ShardedEngine::new(Engine::new(), shards);
+            for tx in transactions() {
+                sharded.process(tx).unwrap();
+            }
+            let (sharded_engine, sharded_rejected) = sharded.finish().unwrap();
+            let mut output = Vec::new();
+            write_ledger(&mut output, sharded_engine.ledger()).unwrap();
+            assert_eq!(output, expected);
+            assert_eq!(
+                sharded_rejected.iter().map(|r| r.index).collect::<Vec<_>>(),
+                rejected
+            );
+        }
+    }
+
+    #[test]
+    fn test_sharded_engine_keeps_limits_for_sequential_continuation() {
+        let mut limits = Limits::new();
+        limits.set_tier(
+            "standard",
+            TierLimits {
+                daily: Some(5.0),
+                ..TierLimits::default()
+            },
+        );
+        limits.set_default_tier("standard");
+        let engine = || Engine::new().with_limits(limits.clone()).with_as_of(0);
+        let first: Vec<Transaction> = (0..8u32)
+            .flat_map(|client| {
+                let client = client as u16;
+                vec![
+                    Transaction::Deposit(client, 2 * client as u32 + 1, 10.0, Currency::EUR),
+                    Transaction::Withdrawal(client, 2 * client as u32 + 2, 4.0, Currency::EUR),
+                ]
+            })
+            .collect();
+
+        let mut sequential = engine();
+        first
+            .iter()
+            .for_each(|tx| sequential.process(tx.clone()).unwrap());
+        let mut sharded = ShardedEngine::new(engine(), 3);
+        first
+            .into_iter()
+            .for_each(|tx| sharded.process(tx).unwrap());
+        let (mut continued, rejected) = sharded.finish().unwrap();
+        assert!(rejected.is_empty());
+
+        // the withdrawals made by the shards still count towards the daily limit
+        for client in 0..8u16 {
+            let tx = Transaction::Withdrawal(client, 100 + client as u32, 2.0, Currency::EUR);
+            assert!(sequential.process(tx.clone()).is_err());
+            assert!(continued.process(tx).is_err());
+        }
+        let (expected, checkpoint) = (
+            sequential.checkpoint("input"),
+            continued.checkpoint("input"),
+        );
+        assert_eq!(checkpoint.records, expected.records);
+        assert_eq!(checkpoint.rejected, expected.rejected);
+        assert_eq!(checkpoint.digest, expected.digest);
+        assert_eq!(checkpoint.withdrawals, expected.withdrawals);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::account::{Amount, Currency, Timestamp};
+
+use anyhow::{anyhow, Result};
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+
+/// How converted amounts are rounded to the configured number of decimals.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum RoundingMode {
+    /// Round to the nearest value, ties are rounded to the even neighbour.
+    HalfEven,
+    /// Round to the nearest value, ties are rounded away from zero.
+    HalfUp,
+    /// Always round towards positive infinity.
+    Up,
+    /// Always round towards negative infinity.
+    Down,
+}
+
+impl FromStr for RoundingMode {
+    type Err = anyhow::Error;
+
+    /// Parses a rounding mode: `half-even`, `half-up`, `up` or `down`.
+    fn from_str(mode: &str) -> Result<RoundingMode> {
+        match mode {
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "half-up" => Ok(RoundingMode::HalfUp),
+            "up" => Ok(RoundingMode::Up),
+            "down" => Ok(RoundingMode::Down),
+            _ => Err(anyhow!("Not a valid rounding mode '{}'", mode)),
+        }
+    }
+}
+
+/// Rounding rules applied to converted amounts.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub struct Rounding {
+    pub decimals: u32,
+    pub mode: RoundingMode,
+}
+
+impl Default for Rounding {
+    fn default() -> Rounding {
+        Rounding {
+            decimals: 4,
+            mode: RoundingMode::HalfEven,
+        }
+    }
+}
+
+impl Rounding {
+    /// Rounds an amount following the rounding rules.
+    pub fn round(&self, amount: Amount) -> Amount {
+        let scale = 10_f64.powi(self.decimals as i32);
+        let scaled = amount * scale;
+        let rounded = match self.mode {
+            RoundingMode::HalfEven => {
+                let floor = scaled.floor();
+                let diff = scaled - floor;
+                if (diff - 0.5).abs() < 1e-9 {
+                    if floor % 2.0 == 0.0 {
+                        floor
+                    } else {
+                        floor + 1.0
+                    }
+                } else {
+                    scaled.round()
+                }
+            }
+            RoundingMode::HalfUp => scaled.round(),
+            RoundingMode::Up => scaled.ceil(),
+            RoundingMode::Down => scaled.floor(),
+        };
+        rounded / scale
+    }
+}
+
+/// A Conversion records how a withdrawal was funded from a balance in another currency.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub struct Conversion {
+    /// Currency the funds were taken from.
+    pub from: Currency,
+    /// Currency of the withdrawal.
+    pub to: Currency,
+    /// Rate applied to the client, units of `to` per unit of `from`, spread included.
+    pub rate: f64,
+    /// Amount debited from the `from` balance.
+    pub amount: Amount,
+    /// Amount withdrawn in the `to` currency.
+    pub converted: Amount,
+}
+
+#[derive(Deserialize, Debug)]
+struct RateRecord {
+    from: Currency,
+    to: Currency,
+    rate: f64,
+    effective: Timestamp,
+}
+
+/// A RateTable holds the exchange rates between currencies along with the time they became effective.
+///
+/// It's loaded from a CSV with the columns `from`, `to`, `rate` and `effective`, where `rate` is the
+/// units of `to` per unit of `from`, and `effective` the timestamp since the rate applies:
+///
+/// ```text
+/// from,to,rate,effective
+/// EUR,USD,1.08,1650000000
+/// EUR,GBP,0.85,1650000000
+/// ```
+///
+/// Rates are also used in the inverse direction when there is no rate given for it.
+#[derive(Debug, Clone, Default)]
+pub struct RateTable {
+    rates: HashMap<(Currency, Currency), Vec<(Timestamp, f64)>>,
+}
+
+impl RateTable {
+    /// Create an empty rate table.
+    pub fn new() -> RateTable {
+        RateTable::default()
+    }
+
+    /// Load the rates from a stream of bytes in CSV format.
+    pub fn from_reader(reader: impl io::Read) -> Result<RateTable> {
+        let rdr = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+        let mut table = RateTable::new();
+        for result in rdr.into_deserialize() {
+            let record: RateRecord = result?;
+            if !(record.rate.is_finite() && record.rate > 0.0) {
+                return Err(anyhow!(
+                    "Not a valid rate {} from {} to {}",
+                    record.rate,
+                    record.from,
+                    record.to
+                ));
+            }
+            table.insert(record.from, record.to, record.rate, record.effective);
+        }
+        Ok(table)
+    }
+
+    /// Adds a rate that becomes effective at `effective`.
+    pub fn insert(&mut self, from: Currency, to: Currency, rate: f64, effective: Timestamp) {
+        let rates = self.rates.entry((from, to)).or_default();
+        rates.push((effective, rate));
+        rates.sort_by_key(|(effective, _)| *effective);
+    }
+
+    /// Returns the rate from one currency to another that is effective at `at`.
+    pub fn rate(&self, from: Currency, to: Currency, at: Timestamp) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        let effective_rate = |from, to| {
+            self.rates.get(&(from, to)).and_then(|rates| {
+                rates
+                    .iter()
+                    .rev()
+                    .find(|(effective, _)| *effective <= at)
+                    .map(|(_, rate)| *rate)
+            })
+        };
+        effective_rate(from, to).or_else(|| effective_rate(to, from).map(|rate| 1.0 / rate))
+    }
+}
+
+/// Fx converts funds between currencies using the rates of a `RateTable`.
+///
+/// Conversions charged to clients apply a `spread`, given as a fraction of the rate (e.g. `0.005` for 0.5%),
+/// and the converted amounts are rounded following the `Rounding` rules.
+#[derive(Debug, Clone, Default)]
+pub struct Fx {
+    rates: RateTable,
+    spread: f64,
+    rounding: Rounding,
+}
+
+impl Fx {
+    /// Create a converter without spread using the default rounding rules.
+    pub fn new(rates: RateTable) -> Fx {
+        Fx {
+            rates,
+            ..Fx::default()
+        }
+    }
+
+    /// Sets the spread applied to the conversions charged to clients.
+    ///
+    /// The spread must be at least 0 and less than 1, as clients would otherwise get no funds, or negative
+    /// ones, for every unit converted.
+    pub fn with_spread(mut self, spread: f64) -> Result<Fx> {
+        if !(0.0..1.0).contains(&spread) {
+            return Err(anyhow!(
+                "Not a valid spread {}, it must be at least 0 and less than 1",
+                spread
+            ));
+        }
+        self.spread = spread;
+        Ok(self)
+    }
+
+    /// Sets the rounding rules of the converted amounts.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Fx {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Quotes the conversion needed for withdrawing `converted` in `to` currency from funds in `from` currency.
+    ///
+    /// The client gets `rate * (1 - spread)` units of `to` per unit of `from`.
+    pub fn quote(
+        &self,
+        from: Currency,
+        to: Currency,
+        converted: Amount,
+        at: Timestamp,
+    ) -> Option<Conversion> {
+        let rate = self.rates.rate(from, to, at)? * (1.0 - self.spread);
+        Some(Conversion {
+            from,
+            to,
+            rate,
+            amount: self.rounding.round(converted / rate),
+            converted,
+        })
+    }
+
+    /// Values an amount in another currency at the rate effective at `at`, without any spread.
+    pub fn value(
+        &self,
+        amount: Amount,
+        from: Currency,
+        to: Currency,
+        at: Timestamp,
+    ) -> Option<Amount> {
+        let rate = self.rates.rate(from, to, at)?;
+        Some(self.rounding.round(amount * rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates() -> RateTable {
+        let input = "\
+from,to,rate,effective
+EUR,USD,1.10,100
+EUR,USD,1.25,200
+EUR,GBP,0.80,100"
+            .as_bytes();
+        RateTable::from_reader(input).expect("failed reading rates")
+    }
+
+    #[test]
+    fn test_rates_are_picked_by_effective_time() {
+        let rates = rates();
+        assert_eq!(rates.rate(Currency::EUR, Currency::USD, 99), None);
+        assert_eq!(rates.rate(Currency::EUR, Currency::USD, 100), Some(1.10));
+        assert_eq!(rates.rate(Currency::EUR, Currency::USD, 199), Some(1.10));
+        assert_eq!(rates.rate(Currency::EUR, Currency::USD, 200), Some(1.25));
+        assert_eq!(rates.rate(Currency::GBP, Currency::EUR, 100), Some(1.25)); // inverse rate
+        assert_eq!(rates.rate(Currency::GBP, Currency::USD, 100), None);
+    }
+
+    #[test]
+    fn test_rate_table_rejects_invalid_rates() {
+        let input = "from,to,rate,effective\nEUR,USD,0,100".as_bytes();
+        assert_eq!(
+            RateTable::from_reader(input).err().unwrap().to_string(),
+            "Not a valid rate 0 from EUR to USD"
+        );
+    }
+
+    #[test]
+    fn test_quote_applies_spread_and_rounding() {
+        let fx = Fx::new(rates())
+            .with_spread(0.2)
+            .expect("failed setting spread")
+            .with_rounding(Rounding {
+                decimals: 2,
+                mode: RoundingMode::Up,
+            });
+        let conversion = fx
+            .quote(Currency::EUR, Currency::USD, 10.0, 200)
+            .expect("missing rate");
+
+        assert_eq!(
+            conversion,
+            Conversion {
+                from: Currency::EUR,
+                to: Currency::USD,
+                rate: 1.0,
+                amount: 10.0,
+                converted: 10.0,
+            }
+        );
+        let conversion = fx.quote(Currency::EUR, Currency::GBP, 1.0, 200).unwrap();
+        assert_eq!(conversion.amount, 1.57); // 1.5625 rounded up
+    }
+
+    #[test]
+    fn test_spread_must_be_a_fraction_below_one() {
+        for spread in [-0.1, 1.0, 2.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                Fx::new(rates())
+                    .with_spread(spread)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                format!(
+                    "Not a valid spread {}, it must be at least 0 and less than 1",
+                    spread
+                )
+            );
+        }
+        assert!(Fx::new(rates()).with_spread(0.0).is_ok());
+        assert!(Fx::new(rates()).with_spread(0.99).is_ok());
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let round = |mode, amount| Rounding { decimals: 1, mode }.round(amount);
+        assert_eq!(round(RoundingMode::HalfEven, 0.25), 0.2);
+        assert_eq!(round(RoundingMode::HalfEven, 0.35), 0.4);
+        assert_eq!(round(RoundingMode::HalfUp, 0.25), 0.3);
+        assert_eq!(round(RoundingMode::Up, 0.21), 0.3);
+        assert_eq!(round(RoundingMode::Down, 0.29), 0.2);
+        assert_eq!(
+            "half-up".parse::<RoundingMode>().unwrap(),
+            RoundingMode::HalfUp
+        );
+    }
+}
@@ -0,0 +1,6 @@
+/*
+ The fx module provides the foreign exchange rates used for converting funds between currencies.
+*/
+mod fx;
+
+pub use fx::{Conversion, Fx, RateTable, Rounding, RoundingMode};
@@ -0,0 +1,311 @@
+use crate::account::{ClientId, Timestamp, Transaction, Tx};
+use crate::fx::Rounding;
+use crate::ledger::Ledger;
+use crate::limits::Tier;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+
+const SECONDS_PER_DAY: Timestamp = 86_400;
+
+/// Day-count convention used for computing the fraction of a year an accrual period spans.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+pub enum DayCount {
+    /// Actual days elapsed over a 365 days year.
+    #[default]
+    #[serde(rename = "act/365")]
+    Act365,
+    /// Actual days elapsed over a 360 days year.
+    #[serde(rename = "act/360")]
+    Act360,
+    /// Months of 30 days over a 360 days year (US bond basis).
+    #[serde(rename = "30/360")]
+    Thirty360,
+}
+
+impl DayCount {
+    /// Returns the fraction of a year between two timestamps.
+    pub fn year_fraction(&self, from: Timestamp, to: Timestamp) -> f64 {
+        let (from, to) = (from / SECONDS_PER_DAY, to / SECONDS_PER_DAY);
+        match self {
+            DayCount::Act365 => to.saturating_sub(from) as f64 / 365.0,
+            DayCount::Act360 => to.saturating_sub(from) as f64 / 360.0,
+            DayCount::Thirty360 => {
+                let (y1, m1, d1) = civil_from_days(from);
+                let (y2, m2, d2) = civil_from_days(to);
+                let d1 = d1.min(30);
+                let d2 = if d1 == 30 { d2.min(30) } else { d2 };
+                let days = 360 * (y2 - y1) + 30 * (m2 - m1) + (d2 - d1);
+                days.max(0) as f64 / 360.0
+            }
+        }
+    }
+}
+
+/// Converts days since the Unix epoch into a (year, month, day) civil date.
+fn civil_from_days(days: u64) -> (i64, i64, i64) {
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// InterestRates is the configuration of the annual interest rates paid to every client tier.
+///
+/// Clients that are not listed use the `default_tier`, and when there is no default tier they don't
+/// accrue any interest. It can be loaded from a JSON file such as:
+///
+/// ```json
+/// {
+///     "day_count": "act/365",
+///     "default_tier": "standard",
+///     "tiers": { "standard": 0.01, "savings": 0.025 },
+///     "clients": { "7": "savings" }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InterestRates {
+    #[serde(default)]
+    day_count: DayCount,
+    #[serde(default)]
+    rounding: Rounding,
+    #[serde(default)]
+    default_tier: Option<Tier>,
+    #[serde(default)]
+    tiers: HashMap<Tier, f64>,
+    #[serde(default)]
+    clients: HashMap<ClientId, Tier>,
+}
+
+impl InterestRates {
+    /// Create a configuration where no client accrues interest.
+    pub fn new(day_count: DayCount) -> InterestRates {
+        InterestRates {
+            day_count,
+            ..InterestRates::default()
+        }
+    }
+
+    /// Load the interest rates from a stream of bytes in JSON format.
+    pub fn from_reader(reader: impl io::Read) -> Result<InterestRates> {
+        let rates: InterestRates = serde_json::from_reader(reader)?;
+        let tiers = rates.clients.values().chain(rates.default_tier.iter());
+        for tier in tiers {
+            if !rates.tiers.contains_key(tier) {
+                return Err(anyhow!(
+                    "interest rates not valid, unknown tier '{}'.",
+                    tier
+                ));
+            }
+        }
+        Ok(rates)
+    }
+
+    /// Sets the annual interest rate of a tier.
+    pub fn set_tier_rate(&mut self, tier: &str, rate: f64) {
+        self.tiers.insert(tier.to_string(), rate);
+    }
+
+    /// Assigns a client to a tier.
+    pub fn set_client_tier(&mut self, client_id: ClientId, tier: &str) {
+        self.clients.insert(client_id, tier.to_string());
+    }
+
+    /// Sets the tier used by clients that have not been assigned to any tier.
+    pub fn set_default_tier(&mut self, tier: &str) {
+        self.default_tier = Some(tier.to_string());
+    }
+
+    /// Returns the annual interest rate paid to a client, if any.
+    pub fn rate(&self, client_id: ClientId) -> Option<f64> {
+        self.clients
+            .get(&client_id)
+            .or(self.default_tier.as_ref())
+            .and_then(|tier| self.tiers.get(tier))
+            .copied()
+    }
+}
+
+/// Accrues the interest of the period between `from` and `to` over the positive available funds of every account.
+///
+/// The interest of every account and currency is posted as a `Transaction::Interest`, so it's kept in the account's
+/// history and cannot be disputed. Postings are made in client id and currency order, taking the transaction ids
+/// from `first_tx` onwards. Frozen accounts don't accrue interest.
+///
+/// Every posting is computed before any of them is made, so the ledger is left untouched when there are not
+/// enough transaction ids left for all of them. It returns the posted transactions, which can be used for
+/// reconciling the accruals.
+pub fn accrue_interest(
+    ledger: &mut Ledger,
+    rates: &InterestRates,
+    from: Timestamp,
+    to: Timestamp,
+    first_tx: Tx,
+) -> Result<Vec<Transaction>> {
+    let year_fraction = rates.day_count.year_fraction(from, to);
+
+    let mut client_ids: Vec<ClientId> = ledger.keys().copied().collect();
+    client_ids.sort_unstable();
+
+    let mut accruals = Vec::new();
+    for client_id in client_ids {
+        let account = &ledger[&client_id];
+        let rate = match rates.rate(client_id) {
+            Some(rate) if !account.is_frozen() => rate,
+            _ => continue,
+        };
+        for (currency, balance) in account.balances() {
+            let interest = rates
+                .rounding
+                .round(balance.available * rate * year_fraction);
+            if interest > 0.0 {
+                accruals.push((client_id, interest, currency));
+            }
+        }
+    }
+    if accruals.len() as u64 > (Tx::MAX - first_tx) as u64 + 1 {
+        return Err(anyhow!(
+            "interest accrual failed, out of transaction ids for {} postings from {}.",
+            accruals.len(),
+            first_tx
+        ));
+    }
+
+    let mut posted = Vec::new();
+    for (tx, (client_id, interest, currency)) in (first_tx..=Tx::MAX).zip(accruals) {
+        let posting = Transaction::Interest(client_id, tx, interest, currency);
+        let account = ledger.get_mut(&client_id).expect("missing account");
+        account.process(posting.clone())?;
+        info!("Interest posted: {:?}", posting);
+        posted.push(posting);
+    }
+    Ok(posted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{Account, Currency};
+
+    const JAN_1_2022: Timestamp = 1_640_995_200;
+    const JAN_31_2022: Timestamp = JAN_1_2022 + 30 * SECONDS_PER_DAY;
+    const MAR_1_2022: Timestamp = JAN_1_2022 + 59 * SECONDS_PER_DAY;
+
+    #[test]
+    fn test_day_count_conventions() {
+        assert_eq!(
+            civil_from_days(JAN_31_2022 / SECONDS_PER_DAY),
+            (2022, 1, 31)
+        );
+        assert_eq!(civil_from_days(MAR_1_2022 / SECONDS_PER_DAY), (2022, 3, 1));
+
+        assert_eq!(
+            DayCount::Act365.year_fraction(JAN_1_2022, MAR_1_2022),
+            59.0 / 365.0
+        );
+        assert_eq!(
+            DayCount::Act360.year_fraction(JAN_1_2022, MAR_1_2022),
+            59.0 / 360.0
+        );
+        assert_eq!(
+            DayCount::Thirty360.year_fraction(JAN_1_2022, MAR_1_2022),
+            60.0 / 360.0
+        );
+        assert_eq!(
+            DayCount::Thirty360.year_fraction(JAN_31_2022, MAR_1_2022),
+            31.0 / 360.0
+        );
+    }
+
+    #[test]
+    fn test_accrue_interest_per_tier() {
+        let mut ledger = Ledger::new();
+        for client_id in 1..=3 {
+            let mut account = Account::new(client_id);
+            account
+                .process(Transaction::Deposit(
+                    client_id,
+                    client_id as Tx,
+                    1000.0,
+                    Currency::EUR,
+                ))
+                .unwrap();
+            ledger.insert(client_id, account);
+        }
+        ledger
+            .get_mut(&3)
+            .unwrap()
+            .process(Transaction::Deposit(3, 4, 360.0, Currency::GBP))
+            .unwrap();
+
+        let mut rates = InterestRates::new(DayCount::Act360);
+        rates.set_tier_rate("savings", 0.1);
+        rates.set_tier_rate("standard", 0.01);
+        rates.set_client_tier(3, "savings");
+        rates.set_client_tier(1, "standard"); // client 2 doesn't accrue any interest
+
+        let posted = accrue_interest(
+            &mut ledger,
+            &rates,
+            JAN_1_2022,
+            JAN_1_2022 + 36 * SECONDS_PER_DAY,
+            100,
+        )
+        .unwrap();
+
+        assert_eq!(
+            posted,
+            vec![
+                Transaction::Interest(1, 100, 1.0, Currency::EUR),
+                Transaction::Interest(3, 101, 10.0, Currency::EUR),
+                Transaction::Interest(3, 102, 3.6, Currency::GBP),
+            ]
+        );
+        assert_eq!(ledger[&1].to_string(), "EUR,1001,0,0,1001,false");
+        assert_eq!(ledger[&2].to_string(), "EUR,1000,0,0,1000,false");
+
+        // the last transaction id can be posted, but the ledger is untouched when the ids run out
+        let period = (JAN_1_2022, JAN_1_2022 + 36 * SECONDS_PER_DAY);
+        let accounts = |ledger: &Ledger| {
+            (1..=3)
+                .map(|client_id| ledger[&client_id].to_string())
+                .collect::<Vec<_>>()
+        };
+        let before = accounts(&ledger);
+        let err = accrue_interest(&mut ledger, &rates, period.0, period.1, Tx::MAX - 1);
+        assert_eq!(
+            err.err().unwrap().to_string(),
+            "interest accrual failed, out of transaction ids for 3 postings from 4294967294."
+        );
+        assert_eq!(accounts(&ledger), before);
+        rates.set_client_tier(1, "none");
+        rates.set_tier_rate("none", 0.0);
+        let posted = accrue_interest(&mut ledger, &rates, period.0, period.1, Tx::MAX - 1).unwrap();
+        assert_eq!(posted.last().map(Transaction::id), Some(Tx::MAX));
+    }
+
+    #[test]
+    fn test_load_interest_rates() {
+        let input = r#"{
+            "day_count": "30/360",
+            "default_tier": "standard",
+            "tiers": { "standard": 0.01, "savings": 0.025 },
+            "clients": { "7": "savings" }
+        }"#
+        .as_bytes();
+        let rates = InterestRates::from_reader(input).expect("failed loading interest rates");
+
+        assert_eq!(rates.day_count, DayCount::Thirty360);
+        assert_eq!(rates.rate(1), Some(0.01));
+        assert_eq!(rates.rate(7), Some(0.025));
+    }
+}
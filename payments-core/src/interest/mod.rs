@@ -0,0 +1,6 @@
+/*
+ The interest module provides the batch job that accrues interest over the balances of a ledger.
+*/
+mod interest;
+
+pub use interest::{accrue_interest, DayCount, InterestRates};
@@ -1,13 +1,48 @@
-use super::account::{Account, ClientId};
+use super::account::{Account, ClientId, Currency, Timestamp};
+use super::fx::Fx;
+use anyhow::Result;
 use std::collections::HashMap;
+use std::io;
+
+mod sink;
+
+pub use sink::{
+    report_consolidated_ledger, report_ledger, AccountSnapshot, CsvSink, JsonSink, LedgerSink,
+    MemorySink,
+};
 
 /// A Ledger is the basic type that hold's a collection of user Accounts.
 pub type Ledger = HashMap<ClientId, Account>;
 
 /// Prints to stdout all of the accounts stored in the Ledger
-pub fn print_ledger(ledger: Ledger) {
-    println!("client, available, held, total, locked");
-    for (key, value) in ledger {
-        println!("{},{}", key, value.to_string());
-    }
+///
+/// Every account is printed in one row per currency, sorted by client id and currency.
+pub fn print_ledger(ledger: &Ledger) {
+    write_ledger(io::stdout().lock(), ledger).expect("failed writing to stdout");
+}
+
+/// Writes all of the accounts stored in the Ledger like `print_ledger`, e.g. into a compressed stream.
+pub fn write_ledger(writer: impl io::Write, ledger: &Ledger) -> Result<()> {
+    report_ledger(ledger, &mut CsvSink::new(writer))
+}
+
+/// Prints to stdout all of the accounts stored in the Ledger like `print_ledger`, adding the
+/// client's total funds in all currencies consolidated in the `base` currency.
+///
+/// Funds are valued at the rates effective at `at`, the consolidated value is left empty when
+/// there's no rate for any of the client's currencies.
+pub fn print_consolidated_ledger(ledger: &Ledger, fx: &Fx, base: Currency, at: Timestamp) {
+    write_consolidated_ledger(io::stdout().lock(), ledger, fx, base, at)
+        .expect("failed writing to stdout");
+}
+
+/// Writes all of the accounts stored in the Ledger like `print_consolidated_ledger`.
+pub fn write_consolidated_ledger(
+    writer: impl io::Write,
+    ledger: &Ledger,
+    fx: &Fx,
+    base: Currency,
+    at: Timestamp,
+) -> Result<()> {
+    report_consolidated_ledger(ledger, fx, base, at, &mut CsvSink::new(writer))
 }
@@ -0,0 +1,259 @@
+use super::Ledger;
+use crate::account::{Account, Amount, Balance, ClientId, Currency, Timestamp};
+use crate::fx::Fx;
+
+use anyhow::Result;
+use serde::Serialize;
+use std::io;
+
+/// The balance of a client's account in one currency at the time of a report.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct AccountSnapshot {
+    pub client: ClientId,
+    pub currency: Currency,
+    #[serde(flatten)]
+    pub balance: Balance,
+    pub locked: bool,
+    /// Total funds of the client in all currencies valued in the base currency of a consolidated report, if there
+    /// are rates for all of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consolidated: Option<Amount>,
+}
+
+/// A LedgerSink receives the snapshots of the accounts of a ledger, e.g. for writing them as CSV or JSON, storing
+/// them in a database or collecting them in tests.
+///
+/// Every report calls `begin`, then `write` with every snapshot sorted by client id and currency, then `finish`.
+pub trait LedgerSink {
+    /// Starts a report, `base` is the currency the funds are consolidated in for consolidated reports.
+    fn begin(&mut self, _base: Option<Currency>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Receives the snapshot of an account's balance in one currency.
+    fn write(&mut self, snapshot: &AccountSnapshot) -> Result<()>;
+
+    /// Ends a report once every snapshot has been written.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Reports every account stored in the Ledger to a sink, in one snapshot per currency.
+pub fn report_ledger(ledger: &Ledger, sink: &mut (impl LedgerSink + ?Sized)) -> Result<()> {
+    sink.begin(None)?;
+    for account in sorted_accounts(ledger) {
+        for snapshot in snapshots(account, None) {
+            sink.write(&snapshot)?;
+        }
+    }
+    sink.finish()
+}
+
+/// Reports every account stored in the Ledger like `report_ledger`, adding the client's total funds in all
+/// currencies consolidated in the `base` currency at the rates effective at `at`.
+pub fn report_consolidated_ledger(
+    ledger: &Ledger,
+    fx: &Fx,
+    base: Currency,
+    at: Timestamp,
+    sink: &mut (impl LedgerSink + ?Sized),
+) -> Result<()> {
+    sink.begin(Some(base))?;
+    for account in sorted_accounts(ledger) {
+        let consolidated: Option<Amount> = account
+            .balances()
+            .iter()
+            .map(|(currency, balance)| fx.value(balance.total, *currency, base, at))
+            .sum();
+        for snapshot in snapshots(account, consolidated) {
+            sink.write(&snapshot)?;
+        }
+    }
+    sink.finish()
+}
+
+fn snapshots(account: &Account, consolidated: Option<Amount>) -> Vec<AccountSnapshot> {
+    account
+        .balances()
+        .into_iter()
+        .map(|(currency, balance)| AccountSnapshot {
+            client: account.client_id(),
+            currency,
+            balance,
+            locked: account.is_frozen(),
+            consolidated,
+        })
+        .collect()
+}
+
+fn sorted_accounts(ledger: &Ledger) -> Vec<&Account> {
+    let mut accounts: Vec<&Account> = ledger.values().collect();
+    accounts.sort_by_key(|account| account.client_id());
+    accounts
+}
+
+/// Writes the snapshots as CSV rows, after a header naming the columns.
+pub struct CsvSink<W: io::Write> {
+    writer: W,
+    consolidated: bool,
+}
+
+impl<W: io::Write> CsvSink<W> {
+    pub fn new(writer: W) -> CsvSink<W> {
+        CsvSink {
+            writer,
+            consolidated: false,
+        }
+    }
+}
+
+impl<W: io::Write> LedgerSink for CsvSink<W> {
+    fn begin(&mut self, base: Option<Currency>) -> Result<()> {
+        write!(
+            self.writer,
+            "client, currency, available, held, authorized, total, locked"
+        )?;
+        if let Some(base) = base {
+            write!(self.writer, ", consolidated_{}", base)?;
+        }
+        self.consolidated = base.is_some();
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn write(&mut self, snapshot: &AccountSnapshot) -> Result<()> {
+        write!(
+            self.writer,
+            "{},{},{},{}",
+            snapshot.client, snapshot.currency, snapshot.balance, snapshot.locked
+        )?;
+        if self.consolidated {
+            let consolidated = snapshot
+                .consolidated
+                .map_or(String::new(), |value| value.to_string());
+            write!(self.writer, ",{}", consolidated)?;
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes every report as a JSON array of snapshots, on its own line.
+pub struct JsonSink<W: io::Write> {
+    writer: W,
+    snapshots: Vec<AccountSnapshot>,
+}
+
+impl<W: io::Write> JsonSink<W> {
+    pub fn new(writer: W) -> JsonSink<W> {
+        JsonSink {
+            writer,
+            snapshots: Vec::new(),
+        }
+    }
+}
+
+impl<W: io::Write> LedgerSink for JsonSink<W> {
+    fn write(&mut self, snapshot: &AccountSnapshot) -> Result<()> {
+        self.snapshots.push(snapshot.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, &self.snapshots)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        self.snapshots.clear();
+        Ok(())
+    }
+}
+
+/// Collects the snapshots of every report in memory.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub snapshots: Vec<AccountSnapshot>,
+}
+
+impl LedgerSink for MemorySink {
+    fn write(&mut self, snapshot: &AccountSnapshot) -> Result<()> {
+        self.snapshots.push(snapshot.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Transaction;
+    use crate::fx::RateTable;
+
+    fn ledger() -> Ledger {
+        let mut ledger = Ledger::new();
+        for tx in [
+            Transaction::Deposit(2, 1, 1.5, Currency::EUR),
+            Transaction::Deposit(1, 2, 2.0, Currency::GBP),
+            Transaction::Deposit(1, 3, 1.0, Currency::EUR),
+        ] {
+            ledger
+                .entry(tx.client_id())
+                .or_insert_with(|| Account::new(tx.client_id()))
+                .process(tx)
+                .unwrap();
+        }
+        ledger
+    }
+
+    #[test]
+    fn test_reporting_to_sinks() {
+        let ledger = ledger();
+        let mut sink = MemorySink::default();
+        report_ledger(&ledger, &mut sink).unwrap();
+        report_ledger(&ledger, &mut sink).unwrap();
+        assert_eq!(sink.snapshots.len(), 6);
+        assert_eq!(
+            sink.snapshots[0],
+            AccountSnapshot {
+                client: 1,
+                currency: Currency::EUR,
+                balance: Balance {
+                    available: 1.0,
+                    total: 1.0,
+                    ..Balance::default()
+                },
+                locked: false,
+                consolidated: None,
+            }
+        );
+
+        let mut csv = CsvSink::new(Vec::new());
+        report_ledger(&ledger, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv.writer).unwrap(),
+            "\
+client, currency, available, held, authorized, total, locked
+1,EUR,1,0,0,1,false
+1,GBP,2,0,0,2,false
+2,EUR,1.5,0,0,1.5,false
+"
+        );
+
+        let rates = "from,to,rate,effective\nGBP,EUR,1.5,0\n".as_bytes();
+        let fx = Fx::new(RateTable::from_reader(rates).unwrap());
+        let mut json = JsonSink::new(Vec::new());
+        report_consolidated_ledger(&ledger, &fx, Currency::EUR, 0, &mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json.writer).unwrap();
+        assert_eq!(
+            json[1],
+            serde_json::json!({
+                "client": 1, "currency": "GBP", "available": 2.0, "held": 0.0, "authorized": 0.0,
+                "total": 2.0, "locked": false, "consolidated": 4.0
+            })
+        );
+    }
+}
@@ -1,11 +1,46 @@
 /*!
 Payments-core is a crate that provides functionality for building the payment system.
 */
+#![allow(clippy::module_inception)]
 
 mod account;
+mod binlog;
+mod checkpoint;
+mod currency;
+mod engine;
+mod fx;
+mod interest;
 mod ledger;
+mod limits;
 mod reader;
+mod validation;
 
-pub use account::{Account, Amount, ClientId, Transaction, Tx};
-pub use ledger::{print_ledger, Ledger};
-pub use reader::reader::load_csv_transactions;
+pub use account::{
+    Account, Amount, Balance, ClientId, Currency, Policy, Reason, RestoreFunds, Timestamp,
+    Transaction, Tx,
+};
+pub use binlog::{is_binary_log, load_binary_transactions, BinaryWriter, BINARY_MAGIC_LEN};
+pub use checkpoint::{Checkpoint, Digest, Verifier};
+pub use engine::{
+    AccountStore, ActorEngine, ClientState, Engine, MemoryStore, Rejected, ShardedEngine,
+};
+pub use fx::{Conversion, Fx, RateTable, Rounding, RoundingMode};
+pub use interest::{accrue_interest, DayCount, InterestRates};
+pub use ledger::{
+    print_consolidated_ledger, print_ledger, report_consolidated_ledger, report_ledger,
+    write_consolidated_ledger, write_ledger, AccountSnapshot, CsvSink, JsonSink, Ledger,
+    LedgerSink, MemorySink,
+};
+pub use limits::{LimitBreach, Limits, RollingWindow, Tier, TierLimits, Withdrawals};
+pub use reader::async_reader::{stream_csv_transactions, stream_csv_transactions_with};
+pub use reader::compression::{compress, decompress, CompressedWriter, Compression};
+pub use reader::config::{Column, Columns, ReaderConfig};
+pub use reader::follow::Follower;
+pub use reader::reader::{
+    load_csv_transactions, load_csv_transactions_lenient, load_csv_transactions_with, ErrorBudget,
+    ReaderError,
+};
+pub use reader::source::{
+    BinarySource, CsvSource, MemorySource, NdjsonSource, Provenance, TransactionSource,
+};
+pub use validation::{validate, ValidationError, MAX_AMOUNT};
@@ -0,0 +1,420 @@
+use crate::account::{Amount, ClientId, Currency, Timestamp};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::{error, fmt, io};
+
+/// Name of a client tier, e.g. `standard` or `premium`.
+pub type Tier = String;
+
+/// Withdrawals accepted for every client, along with the time they were requested and their currency.
+pub type Withdrawals = HashMap<ClientId, VecDeque<(Timestamp, Amount, Currency)>>;
+
+const SECONDS_PER_DAY: Timestamp = 86_400;
+
+/// Caps the sum of the withdrawals made within the last `seconds`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RollingWindow {
+    pub seconds: u64,
+    pub amount: Amount,
+}
+
+/// Withdrawal limits applied to every client of a tier. A missing limit means there is no cap.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct TierLimits {
+    /// Maximum amount of a single withdrawal.
+    #[serde(default)]
+    pub per_transaction: Option<Amount>,
+    /// Maximum amount withdrawn within a calendar day (UTC).
+    #[serde(default)]
+    pub daily: Option<Amount>,
+    /// Maximum amount withdrawn within a rolling window of time.
+    #[serde(default)]
+    pub rolling_window: Option<RollingWindow>,
+}
+
+/// The reason why a withdrawal has been rejected by the limits configuration.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LimitBreach {
+    /// The withdrawal alone is bigger than the per-transaction limit.
+    PerTransaction { limit: Amount, amount: Amount },
+    /// The withdrawal would take the amount withdrawn today over the daily limit.
+    Daily {
+        limit: Amount,
+        withdrawn: Amount,
+        amount: Amount,
+    },
+    /// The withdrawal would take the amount withdrawn in the window over its limit.
+    RollingWindow {
+        seconds: u64,
+        limit: Amount,
+        withdrawn: Amount,
+        amount: Amount,
+    },
+}
+
+impl fmt::Display for LimitBreach {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitBreach::PerTransaction { limit, amount } => write!(
+                f,
+                "withdrawal rejected, amount {} exceeds the per-transaction limit of {}.",
+                amount, limit
+            ),
+            LimitBreach::Daily {
+                limit,
+                withdrawn,
+                amount,
+            } => write!(
+                f,
+                "withdrawal rejected, amount {} exceeds the daily limit of {} ({} already withdrawn).",
+                amount, limit, withdrawn
+            ),
+            LimitBreach::RollingWindow {
+                seconds,
+                limit,
+                withdrawn,
+                amount,
+            } => write!(
+                f,
+                "withdrawal rejected, amount {} exceeds the limit of {} per {} seconds ({} already withdrawn).",
+                amount, limit, seconds, withdrawn
+            ),
+        }
+    }
+}
+
+impl error::Error for LimitBreach {}
+
+/// Limits is the configuration that the engine consults before applying a withdrawal.
+///
+/// Every client belongs to a tier, and every tier defines its own `TierLimits`. Clients that are not
+/// listed use the `default_tier`, and when there is no default tier they are not limited at all.
+///
+/// The limits can be loaded from a JSON file such as:
+///
+/// ```json
+/// {
+///     "default_tier": "standard",
+///     "tiers": {
+///         "standard": { "per_transaction": 500.0, "daily": 1000.0 },
+///         "premium": { "rolling_window": { "seconds": 3600, "amount": 5000.0 } }
+///     },
+///     "clients": { "7": "premium" }
+/// }
+/// ```
+///
+/// Besides the configuration it keeps the withdrawals accepted for every client, which are needed for
+/// evaluating the daily and rolling-window limits.
+///
+/// Limit amounts apply in the currency of every withdrawal: a daily limit of 1000 lets a client withdraw 1000 GBP
+/// and 1000 USD on the same day, as withdrawals in different currencies are not added up.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Limits {
+    #[serde(default)]
+    default_tier: Option<Tier>,
+    #[serde(default)]
+    tiers: HashMap<Tier, TierLimits>,
+    #[serde(default)]
+    clients: HashMap<ClientId, Tier>,
+    #[serde(skip)]
+    withdrawals: Withdrawals,
+}
+
+impl Limits {
+    /// Create a configuration without any limits.
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// Load the limits from a stream of bytes in JSON format.
+    pub fn from_reader(reader: impl io::Read) -> Result<Limits> {
+        let limits: Limits = serde_json::from_reader(reader)?;
+
+        if let Some(tier) = &limits.default_tier {
+            if !limits.tiers.contains_key(tier) {
+                return Err(anyhow!(
+                    "limits not valid, unknown default tier '{}'.",
+                    tier
+                ));
+            }
+        }
+        for (client_id, tier) in &limits.clients {
+            if !limits.tiers.contains_key(tier) {
+                return Err(anyhow!(
+                    "limits not valid, unknown tier '{}' for client {}.",
+                    tier,
+                    client_id
+                ));
+            }
+        }
+        Ok(limits)
+    }
+
+    /// Adds or replaces the limits of a tier.
+    pub fn set_tier(&mut self, tier: &str, limits: TierLimits) {
+        self.tiers.insert(tier.to_string(), limits);
+    }
+
+    /// Assigns a client to a tier.
+    pub fn set_client_tier(&mut self, client_id: ClientId, tier: &str) {
+        self.clients.insert(client_id, tier.to_string());
+    }
+
+    /// Sets the tier used by clients that have not been assigned to any tier.
+    pub fn set_default_tier(&mut self, tier: &str) {
+        self.default_tier = Some(tier.to_string());
+    }
+
+    /// Returns the tier the client belongs to, if any.
+    pub fn tier(&self, client_id: ClientId) -> Option<&Tier> {
+        self.clients.get(&client_id).or(self.default_tier.as_ref())
+    }
+
+    /// Returns the limits that apply to the client, if any.
+    pub fn tier_limits(&self, client_id: ClientId) -> Option<&TierLimits> {
+        self.tier(client_id).and_then(|tier| self.tiers.get(tier))
+    }
+
+    /// Checks whether a withdrawal of `amount` in `currency` requested at `now` is within the client's limits.
+    pub fn check(
+        &self,
+        client_id: ClientId,
+        amount: Amount,
+        currency: Currency,
+        now: Timestamp,
+    ) -> std::result::Result<(), LimitBreach> {
+        let limits = match self.tier_limits(client_id) {
+            Some(limits) => limits,
+            None => return Ok(()),
+        };
+
+        if let Some(limit) = limits.per_transaction {
+            if amount > limit {
+                return Err(LimitBreach::PerTransaction { limit, amount });
+            }
+        }
+
+        if let Some(limit) = limits.daily {
+            let today = now / SECONDS_PER_DAY;
+            let withdrawn = self.withdrawn_since(client_id, currency, today * SECONDS_PER_DAY);
+            if withdrawn + amount > limit {
+                return Err(LimitBreach::Daily {
+                    limit,
+                    withdrawn,
+                    amount,
+                });
+            }
+        }
+
+        if let Some(window) = &limits.rolling_window {
+            let since = (now + 1).saturating_sub(window.seconds);
+            let withdrawn = self.withdrawn_since(client_id, currency, since);
+            if withdrawn + amount > window.amount {
+                return Err(LimitBreach::RollingWindow {
+                    seconds: window.seconds,
+                    limit: window.amount,
+                    withdrawn,
+                    amount,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a withdrawal that has been applied, so it counts towards the daily and rolling-window limits.
+    pub fn record(
+        &mut self,
+        client_id: ClientId,
+        amount: Amount,
+        currency: Currency,
+        now: Timestamp,
+    ) {
+        let retention = match self.tier_limits(client_id) {
+            Some(limits) => {
+                let window = limits.rolling_window.as_ref().map_or(0, |w| w.seconds);
+                window.max(SECONDS_PER_DAY)
+            }
+            None => return, // not limited, nothing to keep track of
+        };
+
+        let withdrawals = self.withdrawals.entry(client_id).or_default();
+        withdrawals.push_back((now, amount, currency));
+        while let Some((timestamp, _, _)) = withdrawals.front() {
+            if *timestamp + retention > now {
+                break;
+            }
+            withdrawals.pop_front();
+        }
+    }
+
+    /// Returns the configuration of the limits, without the withdrawals recorded so far.
+    pub fn configuration(&self) -> Limits {
+        Limits {
+            default_tier: self.default_tier.clone(),
+            tiers: self.tiers.clone(),
+            clients: self.clients.clone(),
+            withdrawals: Withdrawals::new(),
+        }
+    }
+
+    /// Returns the withdrawals that count towards the daily and rolling-window limits.
+    pub fn withdrawals(&self) -> &Withdrawals {
+        &self.withdrawals
+    }
+
+    /// Takes the withdrawals that count towards the limits, leaving none recorded.
+    pub fn take_withdrawals(&mut self) -> Withdrawals {
+        std::mem::take(&mut self.withdrawals)
+    }
+
+    /// Replaces the withdrawals that count towards the limits, e.g. when restoring a checkpoint.
+    pub fn restore_withdrawals(&mut self, withdrawals: Withdrawals) {
+        self.withdrawals = withdrawals;
+    }
+
+    fn withdrawn_since(&self, client_id: ClientId, currency: Currency, since: Timestamp) -> Amount {
+        self.withdrawals.get(&client_id).map_or(0.0, |withdrawals| {
+            withdrawals
+                .iter()
+                .filter(|(timestamp, _, withdrawn)| *timestamp >= since && *withdrawn == currency)
+                .map(|(_, amount, _)| amount)
+                .sum()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(tier_limits: TierLimits) -> Limits {
+        let mut limits = Limits::new();
+        limits.set_tier("standard", tier_limits);
+        limits.set_default_tier("standard");
+        limits
+    }
+
+    #[test]
+    fn test_no_limits_configured() {
+        let limits = Limits::new();
+        assert!(limits.check(1, 1_000_000.0, Currency::EUR, 0).is_ok());
+    }
+
+    #[test]
+    fn test_per_transaction_limit() {
+        let limits = limits(TierLimits {
+            per_transaction: Some(100.0),
+            ..TierLimits::default()
+        });
+        assert!(limits.check(1, 100.0, Currency::EUR, 0).is_ok());
+        assert_eq!(
+            limits.check(1, 100.5, Currency::EUR, 0),
+            Err(LimitBreach::PerTransaction {
+                limit: 100.0,
+                amount: 100.5
+            })
+        );
+    }
+
+    #[test]
+    fn test_daily_limit_resets_next_day() {
+        let mut limits = limits(TierLimits {
+            daily: Some(100.0),
+            ..TierLimits::default()
+        });
+        let day = 19_000 * SECONDS_PER_DAY;
+        limits.record(1, 60.0, Currency::EUR, day + 10);
+
+        assert_eq!(
+            limits.check(1, 50.0, Currency::EUR, day + 20),
+            Err(LimitBreach::Daily {
+                limit: 100.0,
+                withdrawn: 60.0,
+                amount: 50.0
+            })
+        );
+        assert!(limits.check(1, 40.0, Currency::EUR, day + 20).is_ok());
+        assert!(limits
+            .check(1, 50.0, Currency::EUR, day + SECONDS_PER_DAY)
+            .is_ok());
+        assert!(limits.check(2, 50.0, Currency::EUR, day + 20).is_ok()); // other clients are not affected
+    }
+
+    #[test]
+    fn test_rolling_window_limit() {
+        let mut limits = limits(TierLimits {
+            rolling_window: Some(RollingWindow {
+                seconds: 60,
+                amount: 10.0,
+            }),
+            ..TierLimits::default()
+        });
+        limits.record(1, 6.0, Currency::EUR, 100);
+        limits.record(1, 3.0, Currency::EUR, 130);
+
+        assert_eq!(
+            limits.check(1, 2.0, Currency::EUR, 159),
+            Err(LimitBreach::RollingWindow {
+                seconds: 60,
+                limit: 10.0,
+                withdrawn: 9.0,
+                amount: 2.0
+            })
+        );
+        // the first withdrawal falls out of the window
+        assert!(limits.check(1, 2.0, Currency::EUR, 160).is_ok());
+    }
+
+    #[test]
+    fn test_limits_apply_per_currency() {
+        let mut limits = limits(TierLimits {
+            daily: Some(150.0),
+            ..TierLimits::default()
+        });
+        limits.record(1, 100.0, Currency::GBP, 10);
+        limits.record(1, 100.0, Currency::USD, 20);
+
+        // only the GBP withdrawal counts towards a GBP withdrawal
+        assert_eq!(
+            limits.check(1, 60.0, Currency::GBP, 30),
+            Err(LimitBreach::Daily {
+                limit: 150.0,
+                withdrawn: 100.0,
+                amount: 60.0
+            })
+        );
+        assert!(limits.check(1, 50.0, Currency::GBP, 30).is_ok());
+        assert!(limits.check(1, 150.0, Currency::EUR, 30).is_ok());
+    }
+
+    #[test]
+    fn test_load_limits_keyed_by_client_id() {
+        let input = r#"{
+            "default_tier": "standard",
+            "tiers": {
+                "standard": { "per_transaction": 10.0 },
+                "premium": { "per_transaction": 1000.0, "daily": 2000.0 }
+            },
+            "clients": { "7": "premium" }
+        }"#
+        .as_bytes();
+        let limits = Limits::from_reader(input).expect("failed loading limits");
+
+        assert_eq!(limits.tier(1), Some(&"standard".to_string()));
+        assert_eq!(limits.tier(7), Some(&"premium".to_string()));
+        assert!(limits.check(1, 20.0, Currency::EUR, 0).is_err());
+        assert!(limits.check(7, 20.0, Currency::EUR, 0).is_ok());
+    }
+
+    #[test]
+    fn test_load_limits_with_unknown_tier() {
+        let input = r#"{ "tiers": {}, "clients": { "7": "gold" } }"#.as_bytes();
+        let res = Limits::from_reader(input);
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "limits not valid, unknown tier 'gold' for client 7."
+        );
+    }
+}
@@ -0,0 +1,6 @@
+/*
+ The limits module provides the withdrawal limits that the engine consults before applying a withdrawal.
+*/
+mod limits;
+
+pub use limits::{LimitBreach, Limits, RollingWindow, Tier, TierLimits, Withdrawals};
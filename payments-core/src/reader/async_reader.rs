@@ -0,0 +1,123 @@
+use super::config::{Layout, ReaderConfig};
+use super::reader::ReaderError;
+use crate::account::Transaction;
+
+use anyhow::Result;
+use csv_async::{AsyncReader, AsyncReaderBuilder, Trim};
+use futures::stream::{self, Stream};
+use tokio::io::AsyncRead;
+
+/// State of the stream of transactions: the reader and the layout of the records once the headers have been read.
+type ReadState<R> = (AsyncReader<R>, Option<Layout>);
+
+/// Reads transactions from an asynchronous stream of bytes in csv format, such as a socket or a pipe.
+///
+/// Records are read in the same way as `load_csv_transactions`, but one at a time without blocking the runtime.
+/// The returned stream yields every transaction, or the `ReaderError` of the records that cannot be read, which
+/// can be skipped for going on with the next records. It ends after an I/O error.
+pub fn stream_csv_transactions<R>(reader: R) -> impl Stream<Item = Result<Transaction>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    stream_csv_transactions_with(reader, ReaderConfig::default())
+}
+
+/// Reads transactions from an asynchronous stream like `stream_csv_transactions`, following the dialect and
+/// columns of a `ReaderConfig`.
+pub fn stream_csv_transactions_with<R>(
+    reader: R,
+    config: ReaderConfig,
+) -> impl Stream<Item = Result<Transaction>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let rdr = AsyncReaderBuilder::new()
+        .trim(Trim::All)
+        .delimiter(config.delimiter)
+        .quote(config.quote)
+        .quoting(config.quoting)
+        .has_headers(config.has_headers)
+        .create_reader(reader);
+    let state: ReadState<R> = (rdr, None);
+    stream::unfold(Some(state), move |state| {
+        let config = config.clone();
+        async move {
+            let (mut rdr, layout) = state?;
+            let layout = match layout {
+                Some(layout) => layout,
+                None if !config.has_headers => match Layout::new(&config, None) {
+                    Ok(layout) => layout,
+                    Err(err) => return Some((Err(err), None)),
+                },
+                None => match rdr.headers().await {
+                    Ok(headers) => match Layout::new(&config, Some(&to_csv_record(headers))) {
+                        Ok(layout) => layout,
+                        Err(err) => return Some((Err(err), None)),
+                    },
+                    Err(err) => return Some((Err(err.into()), None)),
+                },
+            };
+
+            let mut raw = csv_async::StringRecord::new();
+            let read = rdr.read_record(&mut raw).await;
+            let res = match read {
+                Ok(true) => layout.parse(&to_csv_record(&raw)).map_err(Into::into),
+                Ok(false) => return None,
+                Err(err) if err.is_io_error() => return Some((Err(err.into()), None)),
+                Err(err) => {
+                    let mut error = ReaderError::new(&to_csv_record(&raw), None, err.to_string());
+                    if let Some(pos) = err.position() {
+                        error.line = pos.line();
+                        error.byte = pos.byte();
+                    }
+                    Err(error.into())
+                }
+            };
+            Some((res, Some((rdr, Some(layout)))))
+        }
+    })
+}
+
+/// Converts a record read asynchronously into the record type parsed by the reader, keeping its position.
+fn to_csv_record(record: &csv_async::StringRecord) -> csv::StringRecord {
+    let mut converted: csv::StringRecord = record.iter().collect();
+    converted.set_position(record.position().map(|pos| {
+        let mut position = csv::Position::new();
+        position
+            .set_byte(pos.byte())
+            .set_line(pos.line())
+            .set_record(pos.record());
+        position
+    }));
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_streaming_csv_records() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+depost,1,2,3.0
+withdrawal,1,3,0.5"
+            .as_bytes();
+        let res: Vec<Result<Transaction>> = stream_csv_transactions(input).collect().await;
+
+        assert_eq!(res.len(), 3);
+        assert_eq!(
+            res[0].as_ref().unwrap(),
+            &Transaction::Deposit(1, 1, 1.0, Currency::EUR)
+        );
+        let err = res[1].as_ref().err().unwrap();
+        assert_eq!(err.downcast_ref::<ReaderError>().unwrap().line, 3);
+        assert_eq!(
+            res[2].as_ref().unwrap(),
+            &Transaction::Withdrawal(1, 3, 0.5, Currency::EUR)
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{self, BufReader, Read};
+use std::str::FromStr;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression format of an input or output stream.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Compression {
+    /// Plain, uncompressed bytes.
+    None,
+    /// Gzip compressed bytes, e.g. `.csv.gz` files.
+    Gzip,
+    /// Zstandard compressed bytes, e.g. `.csv.zst` files.
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    /// Parses a compression format: `none`, `gzip` or `zstd`.
+    fn from_str(compression: &str) -> Result<Compression> {
+        match compression {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(anyhow!("Not a valid compression '{}'", compression)),
+        }
+    }
+}
+
+impl Compression {
+    /// Detects the compression format from the first bytes of a stream.
+    pub fn detect(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Wraps a stream so it's decompressed while being read, detecting its compression from its magic bytes.
+///
+/// Streams that are not compressed are read as they are.
+pub fn decompress<'a>(mut reader: impl io::Read + 'a) -> Result<Box<dyn io::Read + 'a>> {
+    // pipes may hand over fewer bytes than the magic ones in a single read
+    let mut magic = [0; ZSTD_MAGIC.len()];
+    let mut len = 0;
+    while len < magic.len() {
+        match reader.read(&mut magic[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let compression = Compression::detect(&magic[..len]);
+    let reader = BufReader::new(io::Cursor::new(magic).take(len as u64).chain(reader));
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+    })
+}
+
+/// A writer compressing everything written to it, created by `compress`.
+///
+/// The compressed stream must be completed through `finish`, which reports the errors of the last writes.
+/// Dropping the writer instead may leave the stream truncated.
+pub struct CompressedWriter<W: io::Write> {
+    encoder: Encoder<W>,
+    written: u64,
+}
+
+enum Encoder<W: io::Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: io::Write> CompressedWriter<W> {
+    /// Returns the number of bytes written to the writer, before compressing them.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Writes the end of the compressed stream and flushes the underlying writer.
+    pub fn finish(self) -> io::Result<()> {
+        let mut writer = match self.encoder {
+            Encoder::None(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+impl<W: io::Write> io::Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match &mut self.encoder {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Wraps a stream so everything written to it is compressed, until the returned writer is finished.
+pub fn compress<W: io::Write>(writer: W, compression: Compression) -> Result<CompressedWriter<W>> {
+    let encoder = match compression {
+        Compression::None => Encoder::None(writer),
+        Compression::Gzip => Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+        Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+    };
+    Ok(CompressedWriter {
+        encoder,
+        written: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A reader handing over a single byte on every read, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((byte, rest)), Some(first)) => {
+                    *first = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn roundtrip(compression: Compression) -> (Vec<u8>, String) {
+        let mut compressed = Vec::new();
+        let mut writer = compress(&mut compressed, compression).unwrap();
+        writer.write_all(b"type,client,tx,amount\n").unwrap();
+        writer.finish().unwrap();
+
+        let mut text = String::new();
+        decompress(Trickle(&compressed))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        (compressed, text)
+    }
+
+    #[test]
+    fn test_compression_is_detected_from_magic_bytes() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let (compressed, text) = roundtrip(compression);
+            assert_eq!(Compression::detect(&compressed), compression);
+            assert_eq!(text, "type,client,tx,amount\n");
+        }
+
+        let mut text = String::new();
+        decompress(Trickle(b"ab"))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn test_compressed_writer_counts_the_bytes_before_compressing() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let mut writer = compress(Vec::new(), compression).unwrap();
+            writer.write_all(b"type,client,tx,amount\n").unwrap();
+            writer.write_all(b"deposit,1,1,1.0\n").unwrap();
+            assert_eq!(writer.written(), 38);
+        }
+    }
+}
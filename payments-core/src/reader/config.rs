@@ -0,0 +1,393 @@
+use super::reader::ReaderError;
+use crate::account::{Currency, Transaction};
+
+use anyhow::{anyhow, Result};
+use csv::{ReaderBuilder, StringRecord, Trim};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+
+/// Names of the transaction types, as given by `Transaction::type_name`.
+const TYPE_NAMES: [&str; 11] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "authorize",
+    "capture",
+    "release",
+    "refund",
+    "interest",
+    "representment",
+];
+
+/// Where a column is found in the records: by its header name, by any of several header names, or by its
+/// position starting at 0.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum Column {
+    Position(usize),
+    Name(String),
+    Names(Vec<String>),
+}
+
+impl Column {
+    /// Finds the index of the column, only positions can be found when there are no headers.
+    fn index(&self, headers: Option<&StringRecord>) -> Option<usize> {
+        let names = match (self, headers) {
+            (Column::Position(position), _) => return Some(*position),
+            (_, None) => return None,
+            (Column::Name(name), _) => std::slice::from_ref(name),
+            (Column::Names(names), _) => names.as_slice(),
+        };
+        headers.and_then(|headers| {
+            names
+                .iter()
+                .find_map(|name| headers.iter().position(|header| header == name))
+        })
+    }
+}
+
+/// The columns holding every field of a transaction. `currency` and `reason` are optional.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct Columns {
+    #[serde(rename = "type")]
+    pub transaction_type: Column,
+    pub client: Column,
+    pub tx: Column,
+    pub amount: Column,
+    pub currency: Option<Column>,
+    pub reason: Option<Column>,
+}
+
+impl Default for Columns {
+    fn default() -> Columns {
+        Columns {
+            transaction_type: Column::Names(vec![
+                "type".to_string(),
+                "transaction_type".to_string(),
+            ]),
+            client: Column::Name("client".to_string()),
+            tx: Column::Name("tx".to_string()),
+            amount: Column::Name("amount".to_string()),
+            currency: Some(Column::Name("currency".to_string())),
+            reason: Some(Column::Name("reason".to_string())),
+        }
+    }
+}
+
+/// ReaderConfig describes the layout of the CSV files of a partner.
+///
+/// It covers the CSV dialect, where every field of a transaction is found, and the keywords the partner uses
+/// for the transaction types besides the standard ones. It can be loaded from a JSON file such as:
+///
+/// ```json
+/// {
+///     "delimiter": ";",
+///     "has_headers": false,
+///     "columns": { "type": 0, "client": 1, "tx": 2, "amount": 4, "currency": null, "reason": null },
+///     "keywords": { "withdraw": "withdrawal", "CB": "chargeback" }
+/// }
+/// ```
+///
+/// Missing settings take the defaults, which read the standard `type,client,tx,amount` files.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct ReaderConfig {
+    /// Delimiter between fields, an ASCII character.
+    #[serde(with = "ascii")]
+    pub delimiter: u8,
+    /// Quote character of the fields, an ASCII character.
+    #[serde(with = "ascii")]
+    pub quote: u8,
+    /// Whether fields can be quoted at all.
+    pub quoting: bool,
+    /// Whether the first record holds the column names.
+    pub has_headers: bool,
+    /// Columns holding every field of a transaction.
+    pub columns: Columns,
+    /// Keywords of the partner for the transaction types, along with the type they stand for.
+    pub keywords: HashMap<String, String>,
+    /// Number of threads parsing the records. With more than one, the input is split in chunks of whole records
+    /// which are parsed in parallel and merged back in the order of the input.
+    pub threads: usize,
+}
+
+impl Default for ReaderConfig {
+    fn default() -> ReaderConfig {
+        ReaderConfig {
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
+            has_headers: true,
+            columns: Columns::default(),
+            keywords: HashMap::new(),
+            threads: 1,
+        }
+    }
+}
+
+impl ReaderConfig {
+    /// Load the configuration from a stream of bytes in JSON format.
+    pub fn from_reader(reader: impl io::Read) -> Result<ReaderConfig> {
+        let config: ReaderConfig = serde_json::from_reader(reader)?;
+        for (keyword, name) in &config.keywords {
+            if !TYPE_NAMES.contains(&name.as_str()) {
+                return Err(anyhow!(
+                    "reader configuration not valid, keyword '{}' stands for unknown type '{}'.",
+                    keyword,
+                    name
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns a CSV reader builder following the dialect of the configuration.
+    pub(super) fn builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .trim(Trim::All)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .quoting(self.quoting)
+            .has_headers(self.has_headers);
+        builder
+    }
+}
+
+/// Reads and writes the ASCII characters of the dialect as one-character strings, rejecting any other character.
+mod ascii {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(byte: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(*byte as char)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        let character = char::deserialize(deserializer)?;
+        if !character.is_ascii() {
+            return Err(D::Error::custom(format!(
+                "reader configuration not valid, '{}' is not an ASCII character.",
+                character
+            )));
+        }
+        Ok(character as u8)
+    }
+}
+
+/// A field of the records, along with the name it's reported with.
+#[derive(Debug)]
+struct Field {
+    index: usize,
+    name: String,
+}
+
+/// Layout locates the fields of the transactions within the records of an input, following a `ReaderConfig`.
+#[derive(Debug)]
+pub(super) struct Layout {
+    transaction_type: Field,
+    client: Field,
+    tx: Field,
+    amount: Field,
+    currency: Option<Field>,
+    reason: Option<Field>,
+    keywords: HashMap<String, String>,
+}
+
+impl Layout {
+    /// Locates the columns of the configuration in the headers of the input, if it has any.
+    pub(super) fn new(config: &ReaderConfig, headers: Option<&StringRecord>) -> Result<Layout> {
+        let field = |column: &Column, name: &str| {
+            column.index(headers).map(|index| Field {
+                index,
+                name: headers
+                    .and_then(|headers| headers.get(index))
+                    .unwrap_or(name)
+                    .to_string(),
+            })
+        };
+        let required = |column: &Column, name: &str| {
+            field(column, name)
+                .ok_or_else(|| anyhow!("reading failed, missing column '{}' in the input.", name))
+        };
+        let columns = &config.columns;
+        Ok(Layout {
+            transaction_type: required(&columns.transaction_type, "type")?,
+            client: required(&columns.client, "client")?,
+            tx: required(&columns.tx, "tx")?,
+            amount: required(&columns.amount, "amount")?,
+            currency: columns
+                .currency
+                .as_ref()
+                .and_then(|column| field(column, "currency")),
+            reason: columns
+                .reason
+                .as_ref()
+                .and_then(|column| field(column, "reason")),
+            keywords: config.keywords.clone(),
+        })
+    }
+
+    /// Parses the transaction of a record.
+    pub(super) fn parse(
+        &self,
+        raw: &StringRecord,
+    ) -> std::result::Result<Transaction, ReaderError> {
+        let kind = self.value(raw, &self.transaction_type)?;
+        let keyword = self
+            .keywords
+            .get(kind)
+            .map_or(kind, String::as_str)
+            .to_string();
+        let client = self.parse_field(raw, &self.client)?;
+        let tx = self.parse_field(raw, &self.tx)?;
+        let amount = self.parse_field(raw, &self.amount)?;
+        let currency = match &self.currency {
+            Some(field) if !raw.get(field.index).unwrap_or_default().is_empty() => {
+                self.parse_field::<Currency>(raw, field)?
+            }
+            _ => Currency::default(),
+        };
+        let reason = self
+            .reason
+            .as_ref()
+            .and_then(|field| raw.get(field.index))
+            .filter(|reason| !reason.is_empty())
+            .map(|reason| reason.to_lowercase());
+
+        let tx = match keyword.as_str() {
+            "deposit" => Transaction::Deposit(client, tx, amount, currency),
+            "withdrawal" => Transaction::Withdrawal(client, tx, amount, currency),
+            "dispute" => Transaction::Dispute(client, tx, amount, reason),
+            "resolve" => Transaction::Resolve(client, tx, amount, reason),
+            "chargeback" => Transaction::Chargeback(client, tx, amount, reason),
+            "authorize" => Transaction::Authorize(client, tx, amount, currency),
+            "capture" => Transaction::Capture(client, tx, amount),
+            "release" => Transaction::Release(client, tx, amount),
+            "refund" => Transaction::Refund(client, tx, amount),
+            "interest" => Transaction::Interest(client, tx, amount, currency),
+            "representment" => Transaction::Representment(client, tx, amount),
+            _ => {
+                let message = format!("Not a valid transaction type '{}'", keyword);
+                return Err(self.error(raw, &self.transaction_type, message));
+            }
+        };
+        Ok(tx)
+    }
+
+    fn value<'a>(
+        &self,
+        raw: &'a StringRecord,
+        field: &Field,
+    ) -> std::result::Result<&'a str, ReaderError> {
+        raw.get(field.index)
+            .ok_or_else(|| self.error(raw, field, "missing field".to_string()))
+    }
+
+    fn parse_field<T>(
+        &self,
+        raw: &StringRecord,
+        field: &Field,
+    ) -> std::result::Result<T, ReaderError>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.value(raw, field)?
+            .parse()
+            .map_err(|err: T::Err| self.error(raw, field, err.to_string()))
+    }
+
+    fn error(&self, raw: &StringRecord, field: &Field, message: String) -> ReaderError {
+        ReaderError::new(raw, Some((field.index, field.name.as_str())), message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::reader::load_csv_transactions_with;
+
+    #[test]
+    fn test_headerless_partner_layout() {
+        let config = r#"{
+            "delimiter": ";",
+            "has_headers": false,
+            "columns": { "type": 0, "client": 1, "tx": 2, "amount": 4, "currency": 3 },
+            "keywords": { "withdraw": "withdrawal", "CB": "chargeback" }
+        }"#
+        .as_bytes();
+        let config = ReaderConfig::from_reader(config).expect("failed loading reader config");
+        let input = "\
+deposit;1;1;GBP;1.5;ignored
+withdraw;1;2;;0.5;ignored
+CB;1;1;;0;ignored"
+            .as_bytes();
+        let res = load_csv_transactions_with(input, &config).expect("failed reading csv records");
+
+        assert_eq!(
+            res,
+            vec![
+                Transaction::Deposit(1, 1, 1.5, Currency::GBP),
+                Transaction::Withdrawal(1, 2, 0.5, Currency::EUR),
+                Transaction::Chargeback(1, 1, 0.0, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renamed_columns() {
+        let config = ReaderConfig {
+            columns: Columns {
+                client: Column::Name("customer".to_string()),
+                amount: Column::Names(vec!["value".to_string(), "amt".to_string()]),
+                ..Columns::default()
+            },
+            ..ReaderConfig::default()
+        };
+        let input = "type,customer,tx,amt\ndeposit,7,1,2.0".as_bytes();
+        let res = load_csv_transactions_with(input, &config).expect("failed reading csv records");
+        assert_eq!(res, vec![Transaction::Deposit(7, 1, 2.0, Currency::EUR)]);
+
+        let input = "type,client,tx,amt\ndeposit,7,1,2.0".as_bytes();
+        assert_eq!(
+            load_csv_transactions_with(input, &config)
+                .err()
+                .unwrap()
+                .to_string(),
+            "reading failed, missing column 'client' in the input."
+        );
+    }
+
+    #[test]
+    fn test_keywords_must_stand_for_known_types() {
+        let config = r#"{ "keywords": { "X": "transfer" } }"#.as_bytes();
+        assert_eq!(
+            ReaderConfig::from_reader(config).err().unwrap().to_string(),
+            "reader configuration not valid, keyword 'X' stands for unknown type 'transfer'."
+        );
+    }
+
+    #[test]
+    fn test_dialect_characters_must_be_ascii() {
+        let config = r#"{ "delimiter": "§" }"#.as_bytes();
+        assert!(ReaderConfig::from_reader(config)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("reader configuration not valid, '§' is not an ASCII character."));
+
+        let config = ReaderConfig {
+            delimiter: b';',
+            ..ReaderConfig::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""delimiter":";""#));
+        assert_eq!(ReaderConfig::from_reader(json.as_bytes()).unwrap(), config);
+    }
+}
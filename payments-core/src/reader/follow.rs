@@ -0,0 +1,215 @@
+use super::config::{Layout, ReaderConfig};
+use super::parallel::{self, Chunk};
+use super::reader::ReaderError;
+use crate::account::Transaction;
+
+use anyhow::Result;
+use csv::StringRecord;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Follower tails a CSV file that keeps growing, reading the records appended to it since the last poll.
+///
+/// Only whole records are read, a record still being written is left for a later poll. The file can be rotated,
+/// either by moving it away and creating a new one or by truncating it, in which case the records left in the
+/// old file are read before starting over with the new one, headers included.
+pub struct Follower {
+    path: PathBuf,
+    config: ReaderConfig,
+    file: Option<File>,
+    /// Identity of the open file, to tell when the path points to a new one.
+    id: u64,
+    /// Byte offset of the file where `pending` starts.
+    offset: u64,
+    /// Line of the file where `pending` starts, starting at 1.
+    line: u64,
+    /// Bytes read from the file and not parsed yet.
+    pending: Vec<u8>,
+    /// Layout of the file along with the number of fields of its records, once the first record is read.
+    layout: Option<(Layout, usize)>,
+}
+
+impl Follower {
+    /// Create a follower of the file at `path`, which doesn't need to exist yet, reading it from the start.
+    pub fn new(path: impl AsRef<Path>, config: ReaderConfig) -> Follower {
+        Follower {
+            path: path.as_ref().to_path_buf(),
+            config,
+            file: None,
+            id: 0,
+            offset: 0,
+            line: 1,
+            pending: Vec::new(),
+            layout: None,
+        }
+    }
+
+    /// Reads the records appended to the file since the last poll, along with the errors of the malformed ones.
+    pub fn poll(&mut self) -> Result<Vec<Result<Transaction, ReaderError>>> {
+        let mut res = Vec::new();
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => Some(metadata),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let rotated = match (&self.file, &metadata) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(_), Some(metadata)) => {
+                file_id(metadata) != self.id
+                    || metadata.len() < self.offset + self.pending.len() as u64
+            }
+        };
+        if rotated {
+            // the rest of the old file is read before moving on
+            self.read(&mut res, true)?;
+            self.file = None;
+        }
+        if self.file.is_none() {
+            if let Some(metadata) = &metadata {
+                self.file = Some(File::open(&self.path)?);
+                self.id = file_id(metadata);
+                self.offset = 0;
+                self.line = 1;
+                self.pending.clear();
+                self.layout = None;
+            }
+        }
+        self.read(&mut res, false)?;
+        Ok(res)
+    }
+
+    /// Reads the open file up to its end and parses its whole records, or every record left when `last`.
+    fn read(&mut self, res: &mut Vec<Result<Transaction, ReaderError>>, last: bool) -> Result<()> {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        file.read_to_end(&mut self.pending)?;
+        let end = if last {
+            self.pending.len()
+        } else {
+            parallel::complete(&self.pending, &self.config)
+        };
+        if end == 0 {
+            return Ok(());
+        }
+
+        let mut chunk = Chunk {
+            start: 0,
+            end,
+            line: self.line,
+        };
+        if self.layout.is_none() {
+            let mut rdr = self.config.builder().from_reader(&self.pending[..end]);
+            // the first record gives the number of fields of all of them, even when it's not a header
+            let first = match rdr.headers() {
+                Ok(first) => first.clone(),
+                Err(err) => return Err(ReaderError::from_csv(&err, &StringRecord::new()).into()),
+            };
+            let headers = if self.config.has_headers {
+                chunk.start = rdr.position().byte() as usize;
+                chunk.line = self.line + rdr.position().line() - 1;
+                Some(&first)
+            } else {
+                None
+            };
+            self.layout = Some((Layout::new(&self.config, headers)?, first.len()));
+        }
+        if let Some((layout, fields)) = &self.layout {
+            res.extend(
+                parallel::parse(&self.pending, &chunk, &self.config, layout, *fields)
+                    .into_iter()
+                    .map(|tx| {
+                        tx.map_err(|mut err| {
+                            err.byte += self.offset;
+                            err
+                        })
+                    }),
+            );
+        }
+
+        let lines = self.pending[chunk.start..end]
+            .iter()
+            .filter(|byte| **byte == b'\n')
+            .count() as u64;
+        self.line = chunk.line + lines;
+        self.offset += end as u64;
+        self.pending.drain(..end);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+    use std::io::Write;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn poll(follower: &mut Follower) -> Vec<Result<Transaction, ReaderError>> {
+        follower.poll().unwrap()
+    }
+
+    #[test]
+    fn test_following_a_growing_file() {
+        let path = std::env::temp_dir().join(format!("follow-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut follower = Follower::new(&path, ReaderConfig::default());
+        assert!(poll(&mut follower).is_empty());
+
+        append(&path, "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,");
+        assert_eq!(
+            poll(&mut follower),
+            vec![Ok(Transaction::Deposit(1, 1, 1.0, Currency::EUR))]
+        );
+        append(&path, "2,2.0\ndepost,1,3,1.0\n");
+        let res = poll(&mut follower);
+        assert_eq!(res[0], Ok(Transaction::Deposit(1, 2, 2.0, Currency::EUR)));
+        let err = res[1].clone().err().unwrap();
+        assert_eq!((err.line, err.byte), (4, 54));
+        assert!(poll(&mut follower).is_empty());
+
+        // rotation, the record left in the old file is read before the new file
+        let rotated = path.with_extension("csv.1");
+        append(&path, "withdrawal,1,4,0.5");
+        fs::rename(&path, &rotated).unwrap();
+        append(&path, "type,client,tx,amount\nwithdrawal,1,5,0.5\n");
+        assert_eq!(
+            poll(&mut follower),
+            vec![
+                Ok(Transaction::Withdrawal(1, 4, 0.5, Currency::EUR)),
+                Ok(Transaction::Withdrawal(1, 5, 0.5, Currency::EUR))
+            ]
+        );
+
+        // truncation
+        fs::write(&path, "type,client,tx,amount\ndeposit,2,6,1.0\n").unwrap();
+        assert_eq!(
+            poll(&mut follower),
+            vec![Ok(Transaction::Deposit(2, 6, 1.0, Currency::EUR))]
+        );
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+}
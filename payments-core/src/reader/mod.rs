@@ -1,7 +1,13 @@
 /*
  The reader module provides  is a low level abstraction that helps reading transactions from a source.
 */
+pub mod async_reader;
+pub mod compression;
+pub mod config;
+pub mod follow;
+mod parallel;
 pub mod reader;
+pub mod source;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,290 @@
+use super::config::{Layout, ReaderConfig};
+use super::reader::ReaderError;
+use crate::account::Transaction;
+
+use anyhow::Result;
+use csv::StringRecord;
+use std::{io, thread};
+
+/// A chunk of the input made of whole records.
+#[derive(Debug, PartialEq)]
+pub(super) struct Chunk {
+    pub(super) start: usize,
+    pub(super) end: usize,
+    /// Line of the input where the chunk starts, starting at 1.
+    pub(super) line: u64,
+}
+
+/// Where the scanner is within a record, following the quoting rules of the CSV reader.
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    StartField,
+    Unquoted,
+    Quoted,
+    QuoteInQuoted,
+}
+
+impl State {
+    fn next(self, byte: u8, config: &ReaderConfig) -> State {
+        let (delimiter, quote) = (config.delimiter, config.quote);
+        match (self, byte) {
+            (State::Quoted, b) if b == quote => State::QuoteInQuoted,
+            (State::Quoted, _) => State::Quoted,
+            (State::QuoteInQuoted, b) if b == quote => State::Quoted,
+            (State::StartField, b) if b == quote && config.quoting => State::Quoted,
+            (_, b) if b == delimiter || b == b'\n' || b == b'\r' => State::StartField,
+            _ => State::Unquoted,
+        }
+    }
+}
+
+/// Returns where a chunk ending with the record ending at the LF `pos` must be cut: right after it, or right
+/// before it after a CR, as the CSV reader locates the record following a CRLF at its LF.
+fn cut(input: &[u8], pos: usize) -> usize {
+    if pos > 0 && input[pos - 1] == b'\r' {
+        pos
+    } else {
+        pos + 1
+    }
+}
+
+/// Returns where the whole records at the start of the input end, 0 when there are none yet.
+pub(super) fn complete(input: &[u8], config: &ReaderConfig) -> usize {
+    let mut end = 0;
+    let mut state = State::StartField;
+    for (pos, byte) in input.iter().enumerate() {
+        state = state.next(*byte, config);
+        if *byte == b'\n' && state == State::StartField {
+            end = cut(input, pos);
+        }
+    }
+    end
+}
+
+/// Reads every record of the input like `reader::read_transactions`, parsing chunks of it on `config.threads`
+/// threads. The transactions and the malformed records are handed back in the order of the input, so the
+/// results are the same as reading it sequentially.
+pub(super) fn read_transactions(
+    mut reader: impl io::Read,
+    config: &ReaderConfig,
+    mut on_error: impl FnMut(ReaderError) -> Result<()>,
+) -> Result<(Vec<Transaction>, u64)> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+
+    let mut rdr = config.builder().from_reader(input.as_slice());
+    // the first record gives the number of fields of all of them, even when it's not a header
+    let first = match rdr.headers() {
+        Ok(first) => first.clone(),
+        Err(err) => return Err(ReaderError::from_csv(&err, &StringRecord::new()).into()),
+    };
+    let (headers, start, line) = if config.has_headers {
+        (
+            Some(&first),
+            rdr.position().byte() as usize,
+            rdr.position().line(),
+        )
+    } else {
+        (None, 0, 1)
+    };
+    let layout = Layout::new(config, headers)?;
+
+    let chunks = split(&input, start, line, config);
+    let (input, layout, fields) = (&input, &layout, first.len());
+    let parsed: Vec<Vec<Result<Transaction, ReaderError>>> = thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .iter()
+            .map(|chunk| scope.spawn(move || parse(input, chunk, config, layout, fields)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("parsing thread panicked"))
+            .collect()
+    });
+
+    let mut res = Vec::new();
+    let mut records = 0;
+    for parsed in parsed {
+        records += parsed.len() as u64;
+        for tx in parsed {
+            match tx {
+                Ok(tx) => res.push(tx),
+                Err(err) => on_error(err)?,
+            }
+        }
+    }
+    Ok((res, records))
+}
+
+/// Splits the records of the input starting at `start` into as many chunks as threads, cutting them at the
+/// ends of line that are not within a quoted field.
+fn split(input: &[u8], start: usize, line: u64, config: &ReaderConfig) -> Vec<Chunk> {
+    let size = (input.len() - start) / config.threads.max(1) + 1;
+
+    let mut chunks = Vec::new();
+    let mut chunk = Chunk {
+        start,
+        end: start,
+        line,
+    };
+    let mut lines = 0;
+    let mut state = State::StartField;
+    for (pos, byte) in input.iter().enumerate().skip(start) {
+        state = state.next(*byte, config);
+        if *byte != b'\n' {
+            continue;
+        }
+        lines += 1;
+        if state == State::StartField && pos + 1 - chunk.start >= size {
+            chunk.end = cut(input, pos);
+            // the LF of a CRLF is left for the next chunk
+            let crlf = (pos + 1 - chunk.end) as u64;
+            let next = Chunk {
+                start: chunk.end,
+                end: chunk.end,
+                line: chunk.line + lines - crlf,
+            };
+            chunks.push(std::mem::replace(&mut chunk, next));
+            lines = crlf;
+        }
+    }
+    if chunk.start < input.len() || chunks.is_empty() {
+        chunk.end = input.len();
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Parses the records of a chunk, locating the malformed ones within the whole input.
+pub(super) fn parse(
+    input: &[u8],
+    chunk: &Chunk,
+    config: &ReaderConfig,
+    layout: &Layout,
+    fields: usize,
+) -> Vec<Result<Transaction, ReaderError>> {
+    let locate = |mut err: ReaderError| {
+        err.line += chunk.line - 1;
+        err.byte += chunk.start as u64;
+        err
+    };
+
+    let mut builder = config.builder();
+    // the number of fields is checked against the first record of the input rather than of the chunk
+    builder.has_headers(false).flexible(true);
+    let mut rdr = builder.from_reader(&input[chunk.start..chunk.end]);
+    let mut raw = StringRecord::new();
+    let mut res = Vec::new();
+    loop {
+        match rdr.read_record(&mut raw) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => {
+                res.push(Err(locate(ReaderError::from_csv(&err, &raw))));
+                continue;
+            }
+        }
+        if raw.len() != fields {
+            let message = format!("found {} fields, but expected {}", raw.len(), fields);
+            res.push(Err(locate(ReaderError::new(&raw, None, message))));
+            continue;
+        }
+        res.push(layout.parse(&raw).map_err(locate));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::reader::{
+        load_csv_transactions_lenient, load_csv_transactions_with, ErrorBudget,
+    };
+    use super::*;
+
+    fn input() -> String {
+        let mut input = "type,client,tx,amount,reason\r\n".to_string();
+        for tx in 1..200 {
+            match tx % 7 {
+                0 => input.push_str(&format!(
+                    "dispute,{},{},0,\"multi\nline, \"\"quoted\"\"\"\n",
+                    tx % 5,
+                    tx - 1
+                )),
+                1 => input.push_str(&format!("deposit,{},{},1.5,\n", tx % 5, tx)),
+                2 => input.push_str(&format!("depost,{},{},1.5,\r\n", tx % 5, tx)),
+                3 => input.push_str(&format!("withdrawal,{},{},0.25,,EUR\n", tx % 5, tx)),
+                _ => input.push_str(&format!("withdrawal,{},{},0.25,\n", tx % 5, tx)),
+            }
+        }
+        input
+    }
+
+    fn read(input: &str, threads: usize) -> (Vec<Transaction>, Vec<ReaderError>) {
+        let config = ReaderConfig {
+            threads,
+            ..ReaderConfig::default()
+        };
+        let mut errors = Vec::new();
+        let transactions = load_csv_transactions_lenient(
+            input.as_bytes(),
+            &config,
+            &ErrorBudget::default(),
+            |err| {
+                errors.push(err.clone());
+                Ok(())
+            },
+        )
+        .unwrap();
+        (transactions, errors)
+    }
+
+    #[test]
+    fn test_splitting_on_record_boundaries() {
+        let input = "type,client\n\"a\nb\",1\nc,2\n";
+        let config = ReaderConfig {
+            threads: 4,
+            ..ReaderConfig::default()
+        };
+        assert_eq!(
+            split(input.as_bytes(), 12, 2, &config),
+            vec![
+                Chunk {
+                    start: 12,
+                    end: 20,
+                    line: 2
+                },
+                Chunk {
+                    start: 20,
+                    end: 24,
+                    line: 4
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parallel_reading_matches_sequential_reading() {
+        let input = input();
+        let (transactions, errors) = read(&input, 1);
+        assert_eq!(errors.len(), 58);
+        for threads in [2, 3, 8, 64] {
+            assert_eq!(
+                read(&input, threads),
+                (transactions.clone(), errors.clone())
+            );
+        }
+
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0,EUR\n";
+        let config = ReaderConfig {
+            threads: 4,
+            ..ReaderConfig::default()
+        };
+        assert_eq!(
+            load_csv_transactions_with(input.as_bytes(), &config)
+                .err()
+                .unwrap()
+                .to_string(),
+            "line 3, byte 38: found 5 fields, but expected 4 in record 'deposit,1,2,1.0,EUR'"
+        );
+    }
+}
@@ -1,48 +1,267 @@
+use super::config::{Layout, ReaderConfig};
+use super::parallel;
 use crate::account::Transaction;
 
 use anyhow::{anyhow, Result};
-use csv::{ReaderBuilder, Trim};
-use serde::{Deserialize, Serialize};
-use std::io;
-
-#[derive(Serialize, Deserialize, Debug)]
-struct TransactionType {
-    #[serde(alias = "type")]
-    transaction_type: String,
-    client: u16,
-    tx: u32,
-    amount: f64,
+use csv::StringRecord;
+use std::ops::Range;
+use std::{error, fmt, io};
+
+/// A ReaderError locates a record of the input that could not be read.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReaderError {
+    /// Line of the input where the record starts, starting at 1.
+    pub line: u64,
+    /// Byte offset of the input where the record starts.
+    pub byte: u64,
+    /// Index of the offending field within the record, if known.
+    pub field: Option<usize>,
+    /// Name of the offending field, taken from the headers when the input has them, if known.
+    pub field_name: Option<String>,
+    /// Byte range of the offending field within `record`, if known.
+    pub span: Option<Range<u32>>,
+    /// Text of the record, with its fields separated by commas.
+    pub record: String,
+    /// What went wrong reading the record.
+    pub message: String,
+}
+
+impl ReaderError {
+    pub(super) fn new(
+        record: &StringRecord,
+        field: Option<(usize, &str)>,
+        message: String,
+    ) -> ReaderError {
+        let (line, byte) = record
+            .position()
+            .map_or((0, 0), |pos| (pos.line(), pos.byte()));
+        ReaderError {
+            line,
+            byte,
+            field: field.map(|(index, _)| index),
+            field_name: field.map(|(_, name)| name.to_string()),
+            // fields are located within the record's own data, as they may hold commas themselves
+            span: field.and_then(|(index, _)| {
+                record
+                    .range(index)
+                    .map(|range| (range.start + index) as u32..(range.end + index) as u32)
+            }),
+            record: record.iter().collect::<Vec<_>>().join(","),
+            message,
+        }
+    }
+
+    pub(super) fn from_csv(err: &csv::Error, record: &StringRecord) -> ReaderError {
+        let mut error = ReaderError::new(record, None, err.to_string());
+        if let Some(pos) = err.position() {
+            error.line = pos.line();
+            error.byte = pos.byte();
+        }
+        if let csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } = err.kind()
+        {
+            error.message = format!("found {} fields, but expected {}", len, expected_len);
+        }
+        error
+    }
+
+    /// Returns the column where the offending field starts within the record, starting at 1.
+    pub fn column(&self) -> usize {
+        self.span
+            .as_ref()
+            .map_or(0, |span| self.record[..span.start as usize].chars().count())
+            + 1
+    }
+
+    /// Formats the error as a compiler-style diagnostic pointing at the offending field of the record.
+    ///
+    /// ```text
+    /// error: Not a valid transaction type 'depost'
+    ///  --> sample.csv:3:1 (byte 38)
+    ///   |
+    /// 3 | depost,1,2,3.0
+    ///   | ^^^^^^ field `type`
+    /// ```
+    pub fn diagnostic(&self, source: &str) -> String {
+        let line = self.line.to_string();
+        let margin = " ".repeat(line.len());
+        let width = self
+            .span
+            .as_ref()
+            .map_or(&self.record[..], |span| {
+                &self.record[span.start as usize..span.end as usize]
+            })
+            .chars()
+            .count()
+            .max(1);
+        let label = match &self.field_name {
+            Some(name) => format!(" field `{}`", name),
+            None => String::new(),
+        };
+        format!(
+            "error: {}\n{}--> {}:{}:{} (byte {})\n{} |\n{} | {}\n{} | {}{}{}",
+            self.message,
+            margin,
+            source,
+            self.line,
+            self.column(),
+            self.byte,
+            margin,
+            line,
+            self.record,
+            margin,
+            " ".repeat(self.column() - 1),
+            "^".repeat(width),
+            label
+        )
+    }
+}
+
+impl fmt::Display for ReaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, byte {}", self.line, self.byte)?;
+        if let Some(name) = &self.field_name {
+            write!(f, ", field `{}`", name)?;
+        }
+        write!(f, ": {} in record '{}'", self.message, self.record)
+    }
 }
+
+impl error::Error for ReaderError {}
+
+/// Limits how many records can be malformed when reading in lenient mode. A missing limit means there is no cap.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ErrorBudget {
+    /// Maximum number of malformed records, reading is aborted as soon as it's exceeded.
+    pub max_errors: Option<u64>,
+    /// Maximum fraction of malformed records over all of the records, checked once the input has been read.
+    pub max_ratio: Option<f64>,
+}
+
+impl ErrorBudget {
+    /// Checks that `errors` malformed records don't exceed the maximum number of them, e.g. while following an
+    /// input that never ends.
+    pub fn check_errors(&self, errors: u64) -> Result<()> {
+        match self.max_errors {
+            Some(max_errors) if errors > max_errors => Err(anyhow!(
+                "reading failed, {} malformed records exceed the maximum of {}.",
+                errors,
+                max_errors
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that `errors` malformed records out of all of the `records` read don't exceed the maximum ratio.
+    pub fn check_ratio(&self, errors: u64, records: u64) -> Result<()> {
+        let ratio = if records == 0 {
+            0.0
+        } else {
+            errors as f64 / records as f64
+        };
+        match self.max_ratio {
+            Some(max_ratio) if ratio > max_ratio => Err(anyhow!(
+                "reading failed, {} of {} records are malformed, exceeding the maximum ratio of {}.",
+                errors,
+                records,
+                max_ratio
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Load transactions from a stream of bytes in csv format
 ///
 /// It receives an object that satisfies the io::Read trait. It can read the transactions
 /// that must be presented in CSV format, and produces a `Vec<Transaction>`,
 /// which can be then sent to the Account struct for further processing them.
-
+///
+/// The `currency` column is optional, rows without a currency use the default one. The `reason` column is
+/// optional as well, and gives the reason code of dispute, resolve and chargeback rows in lowercase.
+///
+/// Records that cannot be read are reported with a `ReaderError` locating them in the input.
 pub fn load_csv_transactions(reader: impl io::Read) -> Result<Vec<Transaction>> {
-    // let rdr = csv::Reader::from_reader(reader).trim(Trim::All);
-    let rdr = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
-    let mut iter = rdr.into_deserialize();
+    load_csv_transactions_with(reader, &ReaderConfig::default())
+}
+
+/// Load transactions from a stream of bytes in csv format like `load_csv_transactions`, following the dialect
+/// and columns of a `ReaderConfig`.
+pub fn load_csv_transactions_with(
+    reader: impl io::Read,
+    config: &ReaderConfig,
+) -> Result<Vec<Transaction>> {
+    read_transactions(reader, config, |err| Err(err.into())).map(|(transactions, _)| transactions)
+}
+
+/// Load transactions from a stream of bytes in csv format like `load_csv_transactions_with`, skipping the
+/// records that cannot be read.
+///
+/// Every malformed record is handed over to `quarantine` along with its error, and reading is aborted with an
+/// error once the malformed records exceed the `budget`.
+pub fn load_csv_transactions_lenient(
+    reader: impl io::Read,
+    config: &ReaderConfig,
+    budget: &ErrorBudget,
+    mut quarantine: impl FnMut(&ReaderError) -> Result<()>,
+) -> Result<Vec<Transaction>> {
+    let mut errors = 0;
+    let (transactions, records) = read_transactions(reader, config, |err| {
+        errors += 1;
+        quarantine(&err)?;
+        budget.check_errors(errors)
+    })?;
+    budget.check_ratio(errors, records)?;
+    Ok(transactions)
+}
+
+/// Reads every record of the input handing over the malformed ones to `on_error`, which decides whether reading
+/// goes on. It returns the transactions read along with the number of records.
+fn read_transactions(
+    reader: impl io::Read,
+    config: &ReaderConfig,
+    mut on_error: impl FnMut(ReaderError) -> Result<()>,
+) -> Result<(Vec<Transaction>, u64)> {
+    if config.threads > 1 {
+        return parallel::read_transactions(reader, config, on_error);
+    }
+    let mut rdr = config.builder().from_reader(reader);
+    let mut raw = StringRecord::new();
+    let headers = match rdr.headers() {
+        Ok(headers) if config.has_headers => Some(headers.clone()),
+        Ok(_) => None,
+        Err(err) => return Err(ReaderError::from_csv(&err, &raw).into()),
+    };
+    let layout = Layout::new(config, headers.as_ref())?;
 
     let mut res = Vec::new();
-    while let Some(result) = iter.next() {
-        let record: TransactionType = result?;
-        let tx = match record.transaction_type.as_str() {
-            "deposit" => Transaction::Deposit(record.client, record.tx, record.amount),
-            "withdrawal" => Transaction::Withdrawal(record.client, record.tx, record.amount),
-            "dispute" => Transaction::Dispute(record.client, record.tx, record.amount),
-            "resolve" => Transaction::Resolve(record.client, record.tx, record.amount),
-            "chargeback" => Transaction::Chargeback(record.client, record.tx, record.amount),
-            _ => return Err(anyhow!("Not a valid transaction type")),
-        };
-        res.push(tx);
+    let mut records = 0;
+    loop {
+        let read = rdr.read_record(&mut raw);
+        match read {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) if err.is_io_error() => return Err(err.into()), // the input cannot be read any further
+            Err(err) => {
+                records += 1;
+                on_error(ReaderError::from_csv(&err, &raw))?;
+                continue;
+            }
+        }
+        records += 1;
+        match layout.parse(&raw) {
+            Ok(tx) => res.push(tx),
+            Err(err) => on_error(err)?,
+        }
     }
-    Ok(res)
+    Ok((res, records))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::account::Currency;
 
     #[test]
     fn test_reading_csv_records() {
@@ -60,12 +279,212 @@ withdrawal,2,5,3.0"
         assert_eq!(
             res,
             vec![
-                Transaction::Deposit(1, 1, 1.0),
-                Transaction::Deposit(2, 2, 2.0),
-                Transaction::Deposit(1, 3, 2.0),
-                Transaction::Withdrawal(1, 4, 1.0),
-                Transaction::Withdrawal(2, 5, 3.0)
+                Transaction::Deposit(1, 1, 1.0, Currency::EUR),
+                Transaction::Deposit(2, 2, 2.0, Currency::EUR),
+                Transaction::Deposit(1, 3, 2.0, Currency::EUR),
+                Transaction::Withdrawal(1, 4, 1.0, Currency::EUR),
+                Transaction::Withdrawal(2, 5, 3.0, Currency::EUR)
             ]
         )
     }
+
+    #[test]
+    fn test_reading_authorization_records() {
+        let input = "\
+type,client,tx,amount
+authorize,1,1,3.0
+capture,1,1,2.0
+release,1,1,0"
+            .as_bytes();
+        let res = load_csv_transactions(input).expect("failed reading csv records");
+
+        assert_eq!(
+            res,
+            vec![
+                Transaction::Authorize(1, 1, 3.0, Currency::EUR),
+                Transaction::Capture(1, 1, 2.0),
+                Transaction::Release(1, 1, 0.0)
+            ]
+        )
+    }
+
+    #[test]
+    fn test_reading_optional_currency_column() {
+        let input = "\
+type,client,tx,amount,currency
+deposit,1,1,1.0,GBP
+deposit,1,2,2.0,
+withdrawal,1,3,1.0,usd"
+            .as_bytes();
+        let res = load_csv_transactions(input).expect("failed reading csv records");
+
+        assert_eq!(
+            res,
+            vec![
+                Transaction::Deposit(1, 1, 1.0, Currency::GBP),
+                Transaction::Deposit(1, 2, 2.0, Currency::EUR),
+                Transaction::Withdrawal(1, 3, 1.0, Currency::USD)
+            ]
+        )
+    }
+
+    #[test]
+    fn test_reading_optional_reason_column() {
+        let input = "\
+type,client,tx,amount,reason
+dispute,1,1,0,Fraud
+resolve,1,1,0,
+chargeback,1,2,0,item-not-received
+deposit,1,3,1.0,fraud"
+            .as_bytes();
+        let res = load_csv_transactions(input).expect("failed reading csv records");
+
+        assert_eq!(
+            res,
+            vec![
+                Transaction::Dispute(1, 1, 0.0, Some("fraud".to_string())),
+                Transaction::Resolve(1, 1, 0.0, None),
+                Transaction::Chargeback(1, 2, 0.0, Some("item-not-received".to_string())),
+                Transaction::Deposit(1, 3, 1.0, Currency::EUR)
+            ]
+        )
+    }
+
+    #[test]
+    fn test_reader_error_locates_the_record() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+depost,1,2,3.0"
+            .as_bytes();
+        let err = load_csv_transactions(input).err().unwrap();
+        let err = err.downcast::<ReaderError>().unwrap();
+
+        assert_eq!(
+            err,
+            ReaderError {
+                line: 3,
+                byte: 38,
+                field: Some(0),
+                field_name: Some("type".to_string()),
+                span: Some(0..6),
+                record: "depost,1,2,3.0".to_string(),
+                message: "Not a valid transaction type 'depost'".to_string(),
+            }
+        );
+        assert_eq!(
+            err.diagnostic("input.csv"),
+            "\
+error: Not a valid transaction type 'depost'
+ --> input.csv:3:1 (byte 38)
+  |
+3 | depost,1,2,3.0
+  | ^^^^^^ field `type`"
+        );
+    }
+
+    #[test]
+    fn test_reader_error_on_malformed_field() {
+        let input = "type,client,tx,amount\ndeposit,1,1,\ndeposit,1,2,1.0,EUR".as_bytes();
+        let err = load_csv_transactions(input).err().unwrap();
+        let err = err.downcast::<ReaderError>().unwrap();
+
+        assert_eq!(err.line, 2);
+        assert_eq!(err.field_name, Some("amount".to_string()));
+        assert_eq!(err.column(), 13);
+        assert_eq!(
+            err.to_string(),
+            "line 2, byte 22, field `amount`: cannot parse float from empty string in record 'deposit,1,1,'"
+        );
+
+        let input = "type,client,tx,amount\ndeposit,1,2,1.0,EUR".as_bytes();
+        let err = load_csv_transactions(input).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "line 2, byte 22: found 5 fields, but expected 4 in record 'deposit,1,2,1.0,EUR'"
+        );
+    }
+
+    #[test]
+    fn test_reader_error_points_at_field_after_quoted_delimiter() {
+        let input = "type,reason,client,tx,amount\ndeposit,\"a, b\",1,2,x".as_bytes();
+        let err = load_csv_transactions(input).err().unwrap();
+        let err = err.downcast::<ReaderError>().unwrap();
+
+        assert_eq!(err.column(), 18);
+        assert_eq!(
+            err.diagnostic("input.csv"),
+            "\
+error: invalid float literal
+ --> input.csv:2:18 (byte 29)
+  |
+2 | deposit,a, b,1,2,x
+  |                  ^ field `amount`"
+        );
+    }
+
+    #[test]
+    fn test_lenient_reading_quarantines_malformed_records() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+depost,1,2,3.0
+deposit,1,3,x
+deposit,1,4,2.0"
+            .as_bytes();
+        let mut quarantined = Vec::new();
+        let res = load_csv_transactions_lenient(
+            input,
+            &ReaderConfig::default(),
+            &ErrorBudget::default(),
+            |err| {
+                quarantined.push(err.line);
+                Ok(())
+            },
+        )
+        .expect("failed reading csv records");
+
+        assert_eq!(
+            res,
+            vec![
+                Transaction::Deposit(1, 1, 1.0, Currency::EUR),
+                Transaction::Deposit(1, 4, 2.0, Currency::EUR)
+            ]
+        );
+        assert_eq!(quarantined, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_lenient_reading_aborts_over_budget() {
+        let input = "type,client,tx,amount\ndepost,1,1,1.0\ndeposit,1,2,x\ndeposit,1,3,1.0";
+        let budget = ErrorBudget {
+            max_errors: Some(1),
+            ..ErrorBudget::default()
+        };
+        let res = load_csv_transactions_lenient(
+            input.as_bytes(),
+            &ReaderConfig::default(),
+            &budget,
+            |_| Ok(()),
+        );
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "reading failed, 2 malformed records exceed the maximum of 1."
+        );
+
+        let budget = ErrorBudget {
+            max_ratio: Some(0.5),
+            ..ErrorBudget::default()
+        };
+        let res = load_csv_transactions_lenient(
+            input.as_bytes(),
+            &ReaderConfig::default(),
+            &budget,
+            |_| Ok(()),
+        );
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "reading failed, 2 of 3 records are malformed, exceeding the maximum ratio of 0.5."
+        );
+    }
 }
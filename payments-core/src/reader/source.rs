@@ -0,0 +1,412 @@
+use super::config::{Layout, ReaderConfig};
+use super::reader::ReaderError;
+use crate::account::Transaction;
+use crate::binlog::load_binary_transactions;
+
+use anyhow::Result;
+use csv::StringRecord;
+use serde_json::Value;
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::vec;
+
+/// Where a transaction comes from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Provenance {
+    /// Name of the source, such as the path of the file.
+    pub source: Arc<str>,
+    /// Position of the transaction within the source: its line in text sources, or its index starting at 1.
+    pub position: u64,
+}
+
+/// A TransactionSource yields the transactions of an input backend one at a time, along with their provenance.
+///
+/// Records that cannot be read are yielded as errors, such as a `ReaderError`, and reading can go on with the
+/// next ones. A source ends after an error that prevents reading any further, such as an I/O error. Sources are
+/// processed with `Engine::run`.
+pub trait TransactionSource {
+    /// Name of the source, given in the provenance of its transactions.
+    fn name(&self) -> &str;
+
+    /// Returns the next transaction along with its provenance, or `None` once the source is exhausted.
+    fn next_transaction(&mut self) -> Option<(Result<Transaction>, Provenance)>;
+}
+
+/// Reads transactions from a stream of bytes in csv format like `load_csv_transactions_with`, one record at a time.
+pub struct CsvSource<R: io::Read> {
+    name: Arc<str>,
+    config: ReaderConfig,
+    rdr: csv::Reader<R>,
+    layout: Option<Layout>,
+    raw: StringRecord,
+    done: bool,
+}
+
+impl<R: io::Read> CsvSource<R> {
+    /// Create a source reading the standard `type,client,tx,amount` layout.
+    pub fn new(name: &str, reader: R) -> CsvSource<R> {
+        CsvSource::with_config(name, reader, ReaderConfig::default())
+    }
+
+    /// Create a source reading the layout described by a `ReaderConfig`.
+    pub fn with_config(name: &str, reader: R, config: ReaderConfig) -> CsvSource<R> {
+        CsvSource {
+            name: name.into(),
+            rdr: config.builder().from_reader(reader),
+            config,
+            layout: None,
+            raw: StringRecord::new(),
+            done: false,
+        }
+    }
+
+    fn provenance(&self, line: u64) -> Provenance {
+        Provenance {
+            source: self.name.clone(),
+            position: line,
+        }
+    }
+
+    /// Locates the columns in the headers of the input, once.
+    fn layout(&mut self) -> Result<()> {
+        if self.layout.is_some() {
+            return Ok(());
+        }
+        let headers = match self.rdr.headers() {
+            Ok(headers) if self.config.has_headers => Some(headers.clone()),
+            Ok(_) => None,
+            Err(err) => return Err(ReaderError::from_csv(&err, &self.raw).into()),
+        };
+        self.layout = Some(Layout::new(&self.config, headers.as_ref())?);
+        Ok(())
+    }
+}
+
+impl<R: io::Read> TransactionSource for CsvSource<R> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_transaction(&mut self) -> Option<(Result<Transaction>, Provenance)> {
+        if self.done {
+            return None;
+        }
+        if let Err(err) = self.layout() {
+            self.done = true;
+            return Some((Err(err), self.provenance(1)));
+        }
+        let read = self.rdr.read_record(&mut self.raw);
+        let mut line = self.raw.position().map_or(0, |pos| pos.line());
+        let res = match (read, &self.layout) {
+            (Ok(false), _) => return None,
+            (Ok(true), Some(layout)) => layout.parse(&self.raw).map_err(Into::into),
+            (Ok(true), None) => unreachable!("the layout is located before reading records"),
+            (Err(err), _) => {
+                // the record may still hold the position of the previous one, the error has the failing one
+                line = err.position().map_or(line, |pos| pos.line());
+                if err.is_io_error() {
+                    self.done = true;
+                    Err(err.into())
+                } else {
+                    Err(ReaderError::from_csv(&err, &self.raw).into())
+                }
+            }
+        };
+        Some((res, self.provenance(line)))
+    }
+}
+
+/// Fields of the transactions in NDJSON, in the order they are handed over to the layout.
+const NDJSON_FIELDS: [&str; 6] = ["type", "client", "tx", "amount", "currency", "reason"];
+
+/// Reads transactions from a stream of newline delimited JSON objects, one per line, such as:
+///
+/// ```json
+/// {"type": "deposit", "client": 1, "tx": 1, "amount": 1.5, "currency": "GBP"}
+/// {"type": "dispute", "client": 1, "tx": 1, "reason": "fraud"}
+/// ```
+///
+/// Fields follow the columns of the CSV files, `transaction_type` can be used for `type` as well, and blank
+/// lines are skipped.
+pub struct NdjsonSource<R: BufRead> {
+    name: Arc<str>,
+    reader: R,
+    layout: Layout,
+    line: u64,
+    /// Byte offset of the current line.
+    byte: u64,
+    /// Byte offset of the next line.
+    next: u64,
+    done: bool,
+}
+
+impl<R: BufRead> NdjsonSource<R> {
+    pub fn new(name: &str, reader: R) -> NdjsonSource<R> {
+        let headers = StringRecord::from(NDJSON_FIELDS.to_vec());
+        NdjsonSource {
+            name: name.into(),
+            reader,
+            layout: Layout::new(&ReaderConfig::default(), Some(&headers))
+                .expect("the NDJSON fields hold every required column"),
+            line: 0,
+            byte: 0,
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// Parses a line into a transaction, through the same layout as CSV records.
+    fn parse(&self, text: &str) -> Result<Transaction, ReaderError> {
+        let locate = |mut err: ReaderError| {
+            err.line = self.line;
+            err.byte = self.byte;
+            err
+        };
+        let record = StringRecord::from(vec![text]);
+        let object = match serde_json::from_str::<Value>(text) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => {
+                let message = "not a JSON object".to_string();
+                return Err(locate(ReaderError::new(&record, None, message)));
+            }
+            Err(err) => return Err(locate(ReaderError::new(&record, None, err.to_string()))),
+        };
+        let mut fields = StringRecord::new();
+        for (index, name) in NDJSON_FIELDS.iter().enumerate() {
+            let value = match object.get(*name) {
+                None if index == 0 => object.get("transaction_type"),
+                value => value,
+            };
+            match value {
+                None | Some(Value::Null) => fields.push_field(""),
+                Some(Value::String(value)) => fields.push_field(value),
+                Some(Value::Number(value)) => fields.push_field(&value.to_string()),
+                Some(value) => {
+                    let message = format!("unexpected value {}", value);
+                    return Err(locate(ReaderError::new(
+                        &fields,
+                        Some((index, name)),
+                        message,
+                    )));
+                }
+            }
+        }
+        self.layout.parse(&fields).map_err(locate)
+    }
+}
+
+impl<R: BufRead> TransactionSource for NdjsonSource<R> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_transaction(&mut self) -> Option<(Result<Transaction>, Provenance)> {
+        let mut text = String::new();
+        loop {
+            if self.done {
+                return None;
+            }
+            text.clear();
+            self.line += 1;
+            self.byte = self.next;
+            let provenance = Provenance {
+                source: self.name.clone(),
+                position: self.line,
+            };
+            match self.reader.read_line(&mut text) {
+                Ok(0) => return None,
+                Ok(read) => self.next += read as u64,
+                Err(err) => {
+                    self.done = true;
+                    return Some((Err(err.into()), provenance));
+                }
+            }
+            match text.trim() {
+                "" => continue,
+                text => return Some((self.parse(text).map_err(Into::into), provenance)),
+            }
+        }
+    }
+}
+
+/// Yields transactions that are already in memory, such as the ones of a test or of a binary log.
+pub struct MemorySource {
+    name: Arc<str>,
+    transactions: vec::IntoIter<(Transaction, Provenance)>,
+}
+
+impl MemorySource {
+    /// Create a source whose transactions are located by their index starting at 1.
+    pub fn new(name: &str, transactions: Vec<Transaction>) -> MemorySource {
+        let name: Arc<str> = name.into();
+        let transactions = transactions
+            .into_iter()
+            .zip(1..)
+            .map(|(tx, position)| {
+                let provenance = Provenance {
+                    source: name.clone(),
+                    position,
+                };
+                (tx, provenance)
+            })
+            .collect();
+        MemorySource::with_provenance(&name, transactions)
+    }
+
+    /// Create a source yielding transactions read beforehand from other sources, keeping their provenance.
+    pub fn with_provenance(
+        name: &str,
+        transactions: Vec<(Transaction, Provenance)>,
+    ) -> MemorySource {
+        MemorySource {
+            name: name.into(),
+            transactions: transactions.into_iter(),
+        }
+    }
+}
+
+impl TransactionSource for MemorySource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_transaction(&mut self) -> Option<(Result<Transaction>, Provenance)> {
+        let (tx, provenance) = self.transactions.next()?;
+        Some((Ok(tx), provenance))
+    }
+}
+
+/// Reads transactions from a binary log written by `BinaryWriter`.
+///
+/// The whole log is loaded when the source is created, so that its checksum is verified before yielding any
+/// transaction. Positions are the indexes of the records starting at 1.
+pub struct BinarySource(MemorySource);
+
+impl BinarySource {
+    pub fn new(name: &str, reader: impl io::Read) -> Result<BinarySource> {
+        let transactions = load_binary_transactions(reader)?;
+        Ok(BinarySource(MemorySource::new(name, transactions)))
+    }
+}
+
+impl TransactionSource for BinarySource {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn next_transaction(&mut self) -> Option<(Result<Transaction>, Provenance)> {
+        self.0.next_transaction()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+    use crate::binlog::BinaryWriter;
+
+    fn drain(source: &mut dyn TransactionSource) -> Vec<(Result<Transaction, String>, u64)> {
+        let mut res = Vec::new();
+        while let Some((tx, provenance)) = source.next_transaction() {
+            assert_eq!(&*provenance.source, source.name());
+            res.push((tx.map_err(|err| err.to_string()), provenance.position));
+        }
+        res
+    }
+
+    #[test]
+    fn test_csv_source() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.0\ndepost,1,2,1.0\nwithdrawal,1,3,0.5";
+        let mut source = CsvSource::new("input.csv", input.as_bytes());
+        assert_eq!(
+            drain(&mut source),
+            vec![
+                (Ok(Transaction::Deposit(1, 1, 1.0, Currency::EUR)), 2),
+                (
+                    Err("line 3, byte 38, field `type`: Not a valid transaction type 'depost' in record 'depost,1,2,1.0'".to_string()),
+                    3
+                ),
+                (Ok(Transaction::Withdrawal(1, 3, 0.5, Currency::EUR)), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_source_locates_records_that_fail_to_read() {
+        let input = b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2\ndeposit,1,3,\xff\ndeposit,1,4,1.0\n";
+        let mut source = CsvSource::new("input.csv", &input[..]);
+        let positions: Vec<u64> = drain(&mut source)
+            .into_iter()
+            .map(|(_, position)| position)
+            .collect();
+        assert_eq!(positions, vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_ndjson_source() {
+        let input = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": 1.5, "currency": "GBP"}
+
+{"transaction_type": "dispute", "client": 1, "tx": 1, "amount": 0, "reason": "Fraud"}
+{"type": "deposit", "client": 1, "tx": 2, "amount": true}
+[1]
+"#;
+        let mut source = NdjsonSource::new("input.ndjson", input.as_bytes());
+        assert_eq!(
+            drain(&mut source),
+            vec![
+                (Ok(Transaction::Deposit(1, 1, 1.5, Currency::GBP)), 1),
+                (
+                    Ok(Transaction::Dispute(1, 1, 0.0, Some("fraud".to_string()))),
+                    3
+                ),
+                (
+                    Err("line 4, byte 163, field `amount`: unexpected value true in record 'deposit,1,2'".to_string()),
+                    4
+                ),
+                (
+                    Err("line 5, byte 221: not a JSON object in record '[1]'".to_string()),
+                    5
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_binary_and_memory_sources() {
+        let transactions = vec![
+            Transaction::Deposit(1, 1, 1.0, Currency::EUR),
+            Transaction::Withdrawal(1, 2, 0.5, Currency::EUR),
+        ];
+        let mut writer = BinaryWriter::new(io::Cursor::new(Vec::new())).unwrap();
+        for tx in &transactions {
+            writer.write(tx).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let expected = vec![
+            (Ok(transactions[0].clone()), 1),
+            (Ok(transactions[1].clone()), 2),
+        ];
+        let mut source = BinarySource::new("input.bin", bytes.as_slice()).unwrap();
+        assert_eq!(drain(&mut source), expected);
+        let mut source = MemorySource::new("memory", transactions.clone());
+        assert_eq!(drain(&mut source), expected);
+
+        // transactions read from other sources keep their provenance
+        let provenance = |position| Provenance {
+            source: "input.csv".into(),
+            position,
+        };
+        let read = vec![
+            (transactions[0].clone(), provenance(7)),
+            (transactions[1].clone(), provenance(9)),
+        ];
+        let mut source = MemorySource::with_provenance("inputs", read);
+        assert_eq!(
+            source.next_transaction().map(|(_, provenance)| provenance),
+            Some(provenance(7))
+        );
+
+        assert!(BinarySource::new("input.bin", &bytes[..40]).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+/*
+ The validation module checks transactions before they are applied to the accounts.
+*/
+mod validation;
+
+pub use validation::{validate, ValidationError, MAX_AMOUNT};
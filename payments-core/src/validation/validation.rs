@@ -0,0 +1,125 @@
+use crate::account::{Amount, Transaction};
+
+use std::{error, fmt};
+
+/// Largest amount a transaction can carry, bigger amounts can no longer be represented with 4 decimals of precision.
+pub const MAX_AMOUNT: Amount = 1_000_000_000_000.0;
+
+/// The reason why a transaction has been rejected before reaching the account.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ValidationError {
+    /// The amount is NaN or infinite.
+    NonFiniteAmount { amount: Amount },
+    /// The amount is below zero.
+    NegativeAmount { amount: Amount },
+    /// The transaction moves funds but its amount is zero.
+    ZeroAmount,
+    /// The amount is bigger than `MAX_AMOUNT`.
+    AmountOutOfRange { amount: Amount },
+}
+
+impl ValidationError {
+    /// Returns the reason code of the rejection, e.g. `negative_amount`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::NonFiniteAmount { .. } => "non_finite_amount",
+            ValidationError::NegativeAmount { .. } => "negative_amount",
+            ValidationError::ZeroAmount => "zero_amount",
+            ValidationError::AmountOutOfRange { .. } => "amount_out_of_range",
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::NonFiniteAmount { amount } => {
+                write!(f, "transaction rejected, amount {} is not finite.", amount)
+            }
+            ValidationError::NegativeAmount { amount } => {
+                write!(f, "transaction rejected, amount {} is negative.", amount)
+            }
+            ValidationError::ZeroAmount => write!(f, "transaction rejected, amount is zero."),
+            ValidationError::AmountOutOfRange { amount } => write!(
+                f,
+                "transaction rejected, amount {} exceeds the maximum of {}.",
+                amount, MAX_AMOUNT
+            ),
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+/// Checks the amount of a transaction before it's applied.
+///
+/// Amounts must be finite, not negative and not bigger than `MAX_AMOUNT`. Transactions moving funds by their own
+/// amount (deposits, withdrawals, authorizations, captures, refunds and interest) cannot have a zero amount, while
+/// the rest of transactions act on the amount of the transaction they refer to and usually leave it at zero.
+pub fn validate(tx: &Transaction) -> Result<(), ValidationError> {
+    let (amount, moves_funds) = match tx {
+        Transaction::Deposit(_, _, amount, _)
+        | Transaction::Withdrawal(_, _, amount, _)
+        | Transaction::Authorize(_, _, amount, _)
+        | Transaction::Interest(_, _, amount, _)
+        | Transaction::Capture(_, _, amount)
+        | Transaction::Refund(_, _, amount) => (*amount, true),
+        Transaction::Dispute(_, _, amount, _)
+        | Transaction::Resolve(_, _, amount, _)
+        | Transaction::Chargeback(_, _, amount, _)
+        | Transaction::Release(_, _, amount)
+        | Transaction::Representment(_, _, amount) => (*amount, false),
+    };
+
+    if !amount.is_finite() {
+        return Err(ValidationError::NonFiniteAmount { amount });
+    }
+    if amount < 0.0 {
+        return Err(ValidationError::NegativeAmount { amount });
+    }
+    if amount == 0.0 && moves_funds {
+        return Err(ValidationError::ZeroAmount);
+    }
+    if amount > MAX_AMOUNT {
+        return Err(ValidationError::AmountOutOfRange { amount });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Currency;
+
+    #[test]
+    fn test_invalid_amounts_are_rejected() {
+        let deposit = |amount| Transaction::Deposit(1, 1, amount, Currency::EUR);
+        assert_eq!(validate(&deposit(1.5)), Ok(()));
+        assert_eq!(
+            validate(&deposit(-50.0)),
+            Err(ValidationError::NegativeAmount { amount: -50.0 })
+        );
+        assert_eq!(validate(&deposit(0.0)), Err(ValidationError::ZeroAmount));
+        assert_eq!(
+            validate(&deposit(f64::INFINITY)).unwrap_err().code(),
+            "non_finite_amount"
+        );
+        assert_eq!(
+            validate(&deposit(f64::NAN)).unwrap_err().code(),
+            "non_finite_amount"
+        );
+        assert_eq!(
+            validate(&deposit(MAX_AMOUNT * 10.0)).unwrap_err().code(),
+            "amount_out_of_range"
+        );
+    }
+
+    #[test]
+    fn test_disputes_can_have_zero_amount() {
+        assert_eq!(validate(&Transaction::Dispute(1, 1, 0.0, None)), Ok(()));
+        assert_eq!(
+            validate(&Transaction::Dispute(1, 1, -1.0, None)),
+            Err(ValidationError::NegativeAmount { amount: -1.0 })
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Result};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::io;
+
+/// Number of recent deposits that can be disputed.
+const DISPUTABLE: usize = 10_000;
+/// Maximum number of rows between a dispute and its resolve or chargeback.
+const SETTLEMENT_DELAY: u64 = 1_000;
+
+/// Settings of the generated stream of transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Number of clients, whose ids start at 1.
+    pub clients: u16,
+    /// Number of rows generated.
+    pub rows: u64,
+    /// Fraction of deposits among the deposits and withdrawals.
+    pub deposit_ratio: f64,
+    /// Fraction of the rows disputing an earlier deposit.
+    pub dispute_rate: f64,
+    /// Fraction of the disputes that are resolved later on.
+    pub resolve_rate: f64,
+    /// Fraction of the disputes that are charged back later on.
+    pub chargeback_rate: f64,
+    /// Fraction of the rows that are malformed or hold invalid values.
+    pub invalid_rate: f64,
+    /// Seed of the random generator, the same seed generates the same rows.
+    pub seed: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            clients: 1000,
+            rows: 100_000,
+            deposit_ratio: 0.6,
+            dispute_rate: 0.01,
+            resolve_rate: 0.6,
+            chargeback_rate: 0.2,
+            invalid_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl Settings {
+    /// Checks that the rates are fractions and that disputes cannot be both resolved and charged back.
+    pub fn validate(&self) -> Result<()> {
+        let rates = [
+            ("deposit ratio", self.deposit_ratio),
+            ("dispute rate", self.dispute_rate),
+            ("resolve rate", self.resolve_rate),
+            ("chargeback rate", self.chargeback_rate),
+            ("invalid rate", self.invalid_rate),
+        ];
+        for (name, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(anyhow!(
+                    "settings not valid, {} {} is not between 0 and 1.",
+                    name,
+                    rate
+                ));
+            }
+        }
+        if self.resolve_rate + self.chargeback_rate > 1.0 {
+            return Err(anyhow!(
+                "settings not valid, resolve and chargeback rates add up to more than 1."
+            ));
+        }
+        if self.clients == 0 {
+            return Err(anyhow!(
+                "settings not valid, there must be at least 1 client."
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A row of the generated CSV: type, client, tx and amount.
+pub type Row = [String; 4];
+
+/// Generator yields the rows of a stream of transactions following some `Settings`.
+///
+/// Clients are picked with a skew towards the lower ids, so a few clients hold most of the activity. Amounts
+/// span several orders of magnitude with 2 decimals. Disputes target recent deposits of the same client, and
+/// the fate of every dispute, resolved, charged back or left open, is decided when it's generated and carried
+/// out a number of rows later.
+pub struct Generator {
+    settings: Settings,
+    rng: ChaCha8Rng,
+    row: u64,
+    tx: u32,
+    deposits: VecDeque<(u16, u32)>,
+    /// Resolves and chargebacks to be generated, by the row they are due.
+    settlements: BinaryHeap<Reverse<(u64, u16, u32, bool)>>,
+}
+
+impl Generator {
+    pub fn new(settings: Settings) -> Generator {
+        Generator {
+            rng: ChaCha8Rng::seed_from_u64(settings.seed),
+            settings,
+            row: 0,
+            tx: 0,
+            deposits: VecDeque::new(),
+            settlements: BinaryHeap::new(),
+        }
+    }
+
+    fn client(&mut self) -> u16 {
+        let skewed = self.rng.gen::<f64>().powi(2);
+        1 + (skewed * self.settings.clients as f64) as u16 % self.settings.clients
+    }
+
+    fn amount(&mut self) -> f64 {
+        let cents = 10f64.powf(self.rng.gen_range(2.0..6.0)).round();
+        cents / 100.0
+    }
+
+    fn row(kind: &str, client: u16, tx: u32, amount: impl ToString) -> Row {
+        [
+            kind.to_string(),
+            client.to_string(),
+            tx.to_string(),
+            amount.to_string(),
+        ]
+    }
+
+    fn invalid(&mut self) -> Row {
+        self.tx += 1;
+        let (client, tx) = (self.client(), self.tx);
+        match self.rng.gen_range(0..5) {
+            0 => Generator::row("depost", client, tx, self.amount()),
+            1 => Generator::row("deposit", client, tx, "1.O"),
+            2 => Generator::row("withdrawal", client, tx, -self.amount()),
+            3 => Generator::row("deposit", client, tx, 0),
+            _ => [
+                "deposit".to_string(),
+                client.to_string(),
+                String::new(),
+                self.amount().to_string(),
+            ],
+        }
+    }
+
+    fn dispute(&mut self) -> Option<Row> {
+        if self.deposits.is_empty() {
+            return None;
+        }
+        let index = self.rng.gen_range(0..self.deposits.len());
+        let (client, tx) = self.deposits.remove(index)?;
+        let fate = self.rng.gen::<f64>();
+        let due = self.row + self.rng.gen_range(1..=SETTLEMENT_DELAY);
+        if fate < self.settings.resolve_rate {
+            self.settlements.push(Reverse((due, client, tx, true)));
+        } else if fate < self.settings.resolve_rate + self.settings.chargeback_rate {
+            self.settlements.push(Reverse((due, client, tx, false)));
+        }
+        Some(Generator::row("dispute", client, tx, 0))
+    }
+
+    fn transfer(&mut self) -> Row {
+        self.tx += 1;
+        let (client, tx, amount) = (self.client(), self.tx, self.amount());
+        if self.rng.gen::<f64>() < self.settings.deposit_ratio {
+            if self.deposits.len() == DISPUTABLE {
+                self.deposits.pop_front();
+            }
+            self.deposits.push_back((client, tx));
+            Generator::row("deposit", client, tx, amount)
+        } else {
+            Generator::row("withdrawal", client, tx, amount)
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        if self.row == self.settings.rows {
+            return None;
+        }
+        self.row += 1;
+        if let Some(Reverse((due, client, tx, resolve))) = self.settlements.peek().copied() {
+            if due <= self.row {
+                self.settlements.pop();
+                let kind = if resolve { "resolve" } else { "chargeback" };
+                return Some(Generator::row(kind, client, tx, 0));
+            }
+        }
+        if self.rng.gen::<f64>() < self.settings.invalid_rate {
+            return Some(self.invalid());
+        }
+        if self.rng.gen::<f64>() < self.settings.dispute_rate {
+            if let Some(row) = self.dispute() {
+                return Some(row);
+            }
+        }
+        Some(self.transfer())
+    }
+}
+
+/// Writes the rows generated following the `settings` as CSV, along with the `type,client,tx,amount` headers.
+pub fn write_csv(settings: Settings, writer: impl io::Write) -> Result<()> {
+    settings.validate()?;
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["type", "client", "tx", "amount"])?;
+    for row in Generator::new(settings) {
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(seed: u64) -> Settings {
+        Settings {
+            clients: 50,
+            rows: 5000,
+            dispute_rate: 0.05,
+            invalid_rate: 0.01,
+            seed,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn test_same_seed_generates_same_rows() {
+        let rows: Vec<Row> = Generator::new(settings(7)).collect();
+        assert_eq!(rows.len(), 5000);
+        assert_eq!(rows, Generator::new(settings(7)).collect::<Vec<_>>());
+        assert_ne!(rows, Generator::new(settings(8)).collect::<Vec<_>>());
+
+        let count = |kind: &str| rows.iter().filter(|row| row[0] == kind).count();
+        assert!(count("deposit") > count("withdrawal"));
+        assert!(count("dispute") > count("resolve") && count("resolve") > count("chargeback"));
+        assert!(count("chargeback") > 0 && count("depost") > 0);
+        assert!(rows.iter().all(|row| row[1]
+            .parse::<u16>()
+            .is_ok_and(|client| (1..=50).contains(&client))));
+    }
+
+    #[test]
+    fn test_settings_are_validated() {
+        assert!(Settings::default().validate().is_ok());
+        let settings = Settings {
+            resolve_rate: 0.8,
+            chargeback_rate: 0.3,
+            ..Settings::default()
+        };
+        assert_eq!(
+            settings.validate().err().unwrap().to_string(),
+            "settings not valid, resolve and chargeback rates add up to more than 1."
+        );
+    }
+}
@@ -0,0 +1,8 @@
+/*!
+Payments-gen is a crate that generates synthetic streams of transactions, for load tests, benchmarks and
+reproducible bug reports.
+*/
+
+mod generator;
+
+pub use generator::{write_csv, Generator, Row, Settings};
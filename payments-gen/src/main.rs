@@ -0,0 +1,76 @@
+/*!
+# payments-gen
+
+Payments-gen writes synthetic streams of transactions in the CSV format read by the payments application, for
+load tests, benchmarks and reproducible bug reports.
+
+```
+$ cargo run -p payments-gen -- --clients 10000 --rows 1000000 --seed 42 --output transactions.csv
+$ cargo run -p payments -- transactions.csv
+```
+
+The number of clients and rows, the mix of deposits and withdrawals, the rates of disputes and of their
+resolves and chargebacks, and the rate of malformed or invalid rows can be configured. The same seed always
+generates the same rows, so a stream can be shared by giving its settings.
+*/
+
+use payments_gen::{write_csv, Settings};
+
+use anyhow::Result;
+use clap::Parser;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Command line arguments of the payments-gen application.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Args {
+    /// Number of clients, whose ids start at 1
+    #[clap(long, default_value = "1000")]
+    clients: u16,
+    /// Number of rows generated
+    #[clap(long, default_value = "100000")]
+    rows: u64,
+    /// Fraction of deposits among the deposits and withdrawals
+    #[clap(long, default_value = "0.6")]
+    deposit_ratio: f64,
+    /// Fraction of the rows disputing an earlier deposit of the same client
+    #[clap(long, default_value = "0.01")]
+    dispute_rate: f64,
+    /// Fraction of the disputes resolved later on
+    #[clap(long, default_value = "0.6")]
+    resolve_rate: f64,
+    /// Fraction of the disputes charged back later on
+    #[clap(long, default_value = "0.2")]
+    chargeback_rate: f64,
+    /// Fraction of the rows that are malformed or hold invalid values
+    #[clap(long, default_value = "0")]
+    invalid_rate: f64,
+    /// Seed of the random generator, the same seed generates the same rows
+    #[clap(long, default_value = "0")]
+    seed: u64,
+    /// CSV file where the rows are written, stdout by default
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+/// function that starts the generator, writing the rows as CSV.
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let settings = Settings {
+        clients: args.clients,
+        rows: args.rows,
+        deposit_ratio: args.deposit_ratio,
+        dispute_rate: args.dispute_rate,
+        resolve_rate: args.resolve_rate,
+        chargeback_rate: args.chargeback_rate,
+        invalid_rate: args.invalid_rate,
+        seed: args.seed,
+    };
+
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    write_csv(settings, output)
+}
@@ -24,16 +24,250 @@ Running in debug mode:
 
 ```
 $ RUST_LOG=debug  cargo run --  sample-frezing-account.csv
-[2022-04-27T12:24:17Z DEBUG payments] Account before: 0,0,0,false, tx: Deposit(2, 1, 3.0)
-[2022-04-27T12:24:17Z DEBUG payments] Account after: 3,0,3,false
-[2022-04-27T12:24:17Z DEBUG payments] Account before: 3,0,3,false, tx: Withdrawal(2, 2, 3.0)
-[2022-04-27T12:24:17Z DEBUG payments] Account after: 0,0,0,false
-[2022-04-27T12:24:17Z DEBUG payments] Account before: 0,0,0,false, tx: Dispute(2, 2, 0.0)
-[2022-04-27T12:24:17Z DEBUG payments] Account after: 0,3,3,false
-[2022-04-27T12:24:17Z DEBUG payments] Account before: 0,3,3,false, tx: Chargeback(2, 2, 0.0)
-[2022-04-27T12:24:17Z DEBUG payments] Account after: 0,0,0,true
-client, available, held, total, locked
-2,0,0,0,true
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account before: EUR,0,0,0,0,false, tx: Deposit(2, 1, 3.0, EUR)
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account after: EUR,3,0,0,3,false
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account before: EUR,3,0,0,3,false, tx: Withdrawal(2, 2, 3.0, EUR)
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account after: EUR,0,0,0,0,false
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account before: EUR,0,0,0,0,false, tx: Dispute(2, 2, 0.0, None)
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account after: EUR,0,3,0,3,false
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account before: EUR,0,3,0,3,false, tx: Chargeback(2, 2, 0.0, None)
+[2022-04-27T12:24:17Z DEBUG payments_core::engine::engine] Account after: EUR,0,0,0,0,true
+client, currency, available, held, authorized, total, locked
+2,EUR,0,0,0,0,true
+```
+
+### Currencies
+
+The input can have an optional `currency` column with the currency code of deposits, withdrawals and authorizations, rows without a currency are in `EUR`. Every client keeps separate funds for each currency, disputes act in the currency of the disputed transaction, and the output has one row per client per currency.
+
+
+### Currency conversion
+
+Withdrawals in a currency the client doesn't hold are funded from the client's funds in another currency when a table of exchange rates is given through `--rates`. The rates are read from a CSV where every rate applies since its `effective` timestamp:
+
+```
+from,to,rate,effective
+EUR,USD,1.08,1650000000
+EUR,GBP,0.85,1650000000
+```
+
+A spread can be charged on the rates with `--spread 0.005`, as a fraction of the rate at least 0 and less than 1, and converted amounts are rounded to `--decimals` following the `--rounding` mode (`half-even`, `half-up`, `up` or `down`). Disputes of converted withdrawals act on the funds they were converted from, at the original rate. With `--base-currency USD` the output adds the client's funds in all currencies consolidated in that currency.
+
+
+### Interest accrual
+
+Interest can be paid on the positive available funds of the clients with `--interest rates.json --interest-from <timestamp> --interest-to <timestamp>`, which accrues the interest of the period after processing the input. The rates file gives the annual rate of every client tier along with the day-count convention (`act/365`, `act/360` or `30/360`):
+
+```json
+{
+    "day_count": "act/365",
+    "default_tier": "standard",
+    "tiers": { "standard": 0.01, "savings": 0.025 },
+    "clients": { "7": "savings" }
+}
+```
+
+Every accrual is posted as an `interest` transaction, which is kept in the account's history and cannot be disputed. The postings can be written for reconciliation with `--interest-postings postings.csv`.
+
+
+### Authorizations and refunds
+
+Besides deposits, withdrawals, disputes, resolutions and chargebacks, the input can contain `authorize` rows that reserve funds of the client, and `capture` or `release` rows referring to the authorization's `tx` for debiting part or all of the reserved funds, or for making them available again. The reserved funds are reported in the `authorized` column, apart from the funds `held` by disputes.
+
+Refunds are given with `refund` rows whose `tx` refers to the deposit being refunded. A deposit can be refunded in several steps as long as the refunds don't exceed the deposited amount, and the refunded funds can no longer be disputed.
+
+### Chargeback representment
+
+A chargeback freezes the account, but the merchant can still re-present the charged-back transaction with a `representment` row whose `tx` refers to it. The representment reverses the chargeback, restoring to the available funds everything that was charged back, or with `--representment-restore requested` only the row's amount up to the charged-back funds. With `--unfreeze-on-representment` the account is unfrozen once every chargeback that froze it has been represented. Every step is kept in the account's history.
+
+### Dispute reasons
+
+Dispute, resolve and chargeback rows can give a reason code in an optional `reason` column, e.g. `fraud` or `item-not-received`. The reason is kept with the disputed transaction and in the account's history, and it's reported in the `dispute_reason` column of the rejections file. By default every chargeback freezes the account, with `--freeze-reasons fraud` only the chargebacks of the given reasons do.
+
+
+### Partner file layouts
+
+Files that don't follow the standard layout can be read by describing it in a JSON file passed through `--reader-config`. It sets the delimiter, the quote character, whether fields can be quoted, whether there is a header row, where every column is found, either by its header name or by its position starting at 0, and the keywords the partner uses for the transaction types:
+
+```json
+{
+    "delimiter": ";",
+    "has_headers": false,
+    "columns": { "type": 0, "client": 1, "tx": 2, "amount": 4, "currency": null, "reason": null },
+    "keywords": { "withdraw": "withdrawal", "CB": "chargeback" }
+}
+```
+
+Extra columns are ignored, and the settings that are left out keep the standard layout.
+
+### Following a growing file
+
+With `--follow` the application keeps tailing a single input file instead of exiting at its end. The ledger is kept in memory, the records appended to the file are processed as they are written, and the file is read every `--poll-interval` milliseconds:
+
+```
+$ cargo run -- gateway.csv --follow --emit-every 60 --emit deltas
+```
+
+Every `--emit-every` seconds, if any transaction has been processed since the last time, the ledger is written to stdout: every account with `--emit snapshot`, the default, or only the accounts changed since the last emission with `--emit deltas`. Only the accounts of the transactions applied count as changed. Malformed records stop following the file, unless `--lenient` is given: they are then skipped and written to the `--quarantine` file, up to `--max-errors` of them. The compression of the outputs and the maximum ratio of malformed records don't apply to an input that never ends, so `--compress` and `--max-error-ratio` cannot be combined with `--follow`. Log rotation is handled both when the file is moved away and a new one is created, and when it's truncated: the records left in the old file are processed before starting over with the new one, headers included.
+
+### Parallel parsing
+
+Large CSV files can be parsed on several threads through `--threads`, or the `threads` setting of the reader configuration. The input is split in chunks of whole records, taking quoted fields spanning several lines into account, and the chunks are parsed in parallel. Their transactions are merged back in the order of the input before reaching the ledger, so the results, including the location of malformed records, are the same as parsing the file on a single thread:
+
+```
+$ cargo run -- history.csv --threads 8
+```
+
+### Sharded processing
+
+Accounts are independent from each other, so the transactions can be applied on several worker threads through `--shards`. Every worker owns the accounts of a shard of the clients, `client_id % shards`, and receives their transactions through a bounded queue in the order of the input, so the transactions of every client are applied in order. Once the input is processed the shards are merged into one ledger, and the output and the rejected transactions are the same as processing the input on a single thread:
+
+```
+$ cargo run -- history.csv --shards 8
+```
+
+Sharded processing cannot be combined with checkpoints or follow mode. Library users can use `payments_core::ShardedEngine` for the same purpose: `ShardedEngine::finish` hands back the engine with the accounts, withdrawals and counters of every shard merged, so it can be checkpointed or go on processing sequentially without forgetting the amounts withdrawn against the limits.
+
+### Several inputs and stdin
+
+Several input files can be given, and they are processed in order against the same ledger. Glob patterns are expanded in alphabetical order, and `-` reads the transactions from stdin, so the application can be part of a pipeline:
+
+```
+$ cat january.csv | cargo run -- - 'february-*.csv' --summary summary.csv
+```
+
+Rejected and quarantined records state the file they were read from in their `file` column, and the statistics of every file (records read, malformed, skipped when resuming from a checkpoint, applied and rejected) are written to the CSV file passed through `--summary`.
+
+### Binary transaction log
+
+Large inputs that are replayed often can be converted once into a compact binary log through `--write-binary`, which holds every transaction read from the inputs in a fixed-width record. Binary logs are detected by their header and can be given as inputs in place of CSV files, skipping the CSV parsing:
+
+```
+$ cargo run -- 'history-*.csv' --write-binary history.bin
+$ cargo run -- history.bin
+```
+
+The header carries the version of the encoding and a checksum of the records, so logs written by an incompatible version or corrupted on disk are rejected before processing anything. Reasons of up to 20 bytes are stored within their records, and longer ones once in a table following the records.
+
+### Output formats and ledger sinks
+
+The ledger is written to stdout as CSV by default, or as a JSON array of accounts with `--output json`:
+
+```
+$ cargo run -- sample.csv --output json
+[{"client":1,"currency":"EUR","available":1.5,"held":0.0,"authorized":0.0,"total":1.5,"locked":false}]
+```
+
+Reports go through a `payments_core::LedgerSink`, which receives the snapshot of every account's balance in one currency, sorted by client and currency. `CsvSink`, `JsonSink` and `MemorySink`, which collects the snapshots for tests, are provided, and library users can implement the trait for other destinations such as a database. `report_ledger` and `report_consolidated_ledger` borrow the ledger, so it can be reported more than once, as it's done in follow mode.
+
+### Transaction sources
+
+Besides CSV files and binary logs, inputs with the `.ndjson` or `.jsonl` extension are read as newline delimited JSON, with one transaction per line using the same fields as the CSV columns:
+
+```json
+{"type": "deposit", "client": 1, "tx": 1, "amount": 1.5, "currency": "GBP"}
+```
+
+Every input is read through a `payments_core::TransactionSource`, which yields the transactions along with their provenance: the name of the source and the position of the transaction in it. `CsvSource`, `NdjsonSource`, `BinarySource` and `MemorySource` are provided, and library users can implement the trait for their own backends and process them with `Engine::run`, which hands over the rejected transactions along with their provenance and stops at the first error its callback returns. The command line streams its inputs the same way, one record at a time, so only CSV inputs parsed on several threads are held in memory as a whole: the rejected transactions state their position in their file in the `position` column, which is their line in CSV and NDJSON inputs, or their index in binary logs and in CSV inputs parsed on several threads, and `--lenient` quarantines the malformed records of every format.
+
+### Asynchronous ingestion
+
+Services running on tokio can use `payments_core::stream_csv_transactions`, which reads the transactions from any `AsyncRead`, such as a socket or a pipe, as a `Stream`. The stream can be handed over to `Engine::process_stream`, which applies every transaction as it arrives without blocking the runtime.
+
+### Actor engine for async services
+
+Services running on tokio can embed `payments_core::ActorEngine`, which keeps the account of every active client in its own task with a mailbox. A router task dispatches every transaction to the actor of its client. The transactions that don't fit in a full mailbox are queued until the actor catches up, so a busy client doesn't hold up the others, and once a client has queued as many as its mailbox holds the router waits for it, so that callers are slowed down rather than piling up transactions in memory. `ActorEngine::process` returns once the transaction has been applied or rejected, so callers can await the outcome of each one:
+
+```rust
+let store = Arc::new(MemoryStore::new());
+let engine = ActorEngine::spawn(Engine::new(), store.clone(), Duration::from_secs(300));
+engine.process(Transaction::Deposit(1, 1, 10.0, Currency::EUR)).await?;
+engine.shutdown().await?;
+```
+
+Actors idle for longer than the timeout are evicted: their account, along with the withdrawals evaluated by the limits, is saved into an `AccountStore` and loaded again when the client becomes active. The accounts already held by the engine given to `ActorEngine::spawn` are moved into the store when it starts, and every actor shares only the configuration of that engine: its limits, converter, policy and time. `MemoryStore` keeps them in memory, and the trait can be implemented for a database.
+
+### Compressed input and output
+
+Input files compressed with gzip or zstd, such as `.csv.gz` or `.csv.zst` files, are detected from their first bytes and decompressed while they are read. The ledger and the CSV files written can be compressed the same way with `--compress gzip` or `--compress zstd`:
+
+```
+$ cargo run -- transactions.csv.zst --compress gzip > accounts.csv.gz
+```
+
+### Malformed input
+
+Processing stops at the first record that cannot be read, reporting where it is in the input along with the offending field:
+
+```
+error: Not a valid transaction type 'depost'
+ --> sample.csv:3:1 (byte 38)
+  |
+3 | depost,1,2,3.0
+  | ^^^^^^ field `type`
+```
+
+With `--lenient` the records that cannot be read are skipped instead, and written along with their error to the CSV file passed through `--quarantine`. The run is still aborted when there are more malformed records than `--max-errors`, or once the input has been read when their fraction of all the records exceeds `--max-error-ratio`:
+
+```
+$ cargo run -- sample.csv --lenient --quarantine quarantine.csv --max-error-ratio 0.01
+```
+
+### Amount validation
+
+Transactions are validated before reaching the client's account. Amounts that are not a number or infinite, negative, zero for the transactions moving funds by their own amount, or bigger than 10^12 are rejected, and the rejections file gives them the reason codes `non_finite_amount`, `negative_amount`, `zero_amount` and `amount_out_of_range` in its `code` column.
+
+### Checkpoints
+
+With `--checkpoint checkpoint.json` the number of processed records, a digest of them and the resulting ledger are saved every `--checkpoint-every` records (10000 by default) and once the input has been processed. When the checkpoint file exists, processing the same input again first verifies that it starts with the processed records and then resumes after them, so no transaction is applied twice. When resuming, the rejections file keeps the rejections of the records before the checkpoint, which records how many bytes of them were written, and the rejections written after it are discarded as those records are processed again.
+
+### Withdrawal limits and rejected transactions
+
+Withdrawals can be limited per client tier with a JSON file passed through `--limits`. Every tier can define a per-transaction, a daily and a rolling-window limit, and clients are assigned to tiers by their client id:
+
+```json
+{
+    "default_tier": "standard",
+    "tiers": {
+        "standard": { "per_transaction": 500.0, "daily": 1000.0 },
+        "premium": { "rolling_window": { "seconds": 3600, "amount": 5000.0 } }
+    },
+    "clients": { "7": "premium" }
+}
+```
+
+Limit amounts apply in the currency of every withdrawal, and withdrawals in different currencies are not added up: with a daily limit of 1000 a client can withdraw 1000 GBP and 1000 USD on the same day. Checkpoints record the currency of the withdrawals counting towards the limits.
+
+The input files carry no timestamps, so every transaction of a run is evaluated against the limits, and converted at the rates effective, as of the time the run starts. Replays of historical files can give that time through `--as-of <timestamp>`, so they accept and reject the same transactions whenever they are run, and a run resumed from a checkpoint should be given the same `--as-of` as the original one. In follow mode the transactions are evaluated at the time they are read.
+
+Transactions that cannot be applied are written along with their rejection reason to the CSV file passed through `--rejections`:
+
+```
+$ cargo run -- sample.csv --limits limits.json --rejections rejections.csv
+```
+
+### Generating transactions
+
+The `payments-gen` binary writes synthetic streams of transactions for load tests, benchmarks and bug reports. The number of clients and rows, the mix of deposits and withdrawals, the rate of disputes along with the fractions of them resolved or charged back later on, and the rate of malformed or invalid rows are configurable. The same `--seed` always generates the same rows:
+
+```
+$ cargo run -p payments-gen -- --clients 10000 --rows 1000000 --dispute-rate 0.02 --invalid-rate 0.001 --seed 42 --output transactions.csv
+$ cargo run -- transactions.csv --lenient
+```
+
+
+### Benchmarks
+
+The criterion benchmarks of `payments-core` cover parsing CSV inputs, applying every kind of transaction along the paths of a dispute, and whole runs over inputs written by `payments-gen`. Results are reported as transactions or bytes per second, and criterion compares them with the previous run to spot regressions:
+
+```
+$ cargo bench -p payments-core --bench reader --bench account
+```
+
+The memory held by the engine per million recorded transactions is measured by counting the bytes allocated:
+
+```
+$ cargo bench -p payments-core --bench memory
 ```
 
 
@@ -51,7 +285,11 @@ The project is organized in the following way:
 │   └── src
 ├── payments-core # framework/library that provides a set of tools for building the payment application
 │   ├── Cargo.toml # crate providing a library
+│   ├── benches # criterion benchmarks
 │   └── src
+├── payments-gen # binary writing synthetic streams of transactions
+│   ├── Cargo.toml
+│   └── src
 ├── README.md
 ├── sample.csv # sample csv file
 ├── sample-frezing-account.csv # another sample csv file
@@ -60,50 +298,930 @@ The project is organized in the following way:
 ```
 */
 
-use payments_core::{Ledger, *};
+use payments_core::*;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+use clap::Parser;
+use log::{info, warn};
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use log::debug;
+/// Command line arguments of the payments application.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Args {
+    /// CSV files containing the transactions to process in order, `-` reads stdin and glob patterns are expanded
+    #[clap(required = true)]
+    inputs: Vec<String>,
+    /// JSON file describing the CSV dialect, columns and type keywords of the input files
+    #[clap(long)]
+    reader_config: Option<PathBuf>,
+    /// Number of threads parsing every CSV input file, overriding the reader configuration
+    #[clap(long)]
+    threads: Option<usize>,
+    /// CSV file where the statistics of every input file are written
+    #[clap(long)]
+    summary: Option<PathBuf>,
+    /// JSON file with the withdrawal limits of every client tier
+    #[clap(long)]
+    limits: Option<PathBuf>,
+    /// CSV file where the rejected transactions are written along with their rejection reason
+    #[clap(long)]
+    rejections: Option<PathBuf>,
+    /// CSV file with the exchange rates used for funding withdrawals in currencies the client doesn't hold
+    #[clap(long)]
+    rates: Option<PathBuf>,
+    /// Spread applied to the exchange rates, as a fraction of the rate at least 0 and less than 1
+    #[clap(long, default_value = "0", parse(try_from_str = parse_spread))]
+    spread: f64,
+    /// Number of decimals converted amounts are rounded to
+    #[clap(long, default_value = "4")]
+    decimals: u32,
+    /// Rounding mode of converted amounts: half-even, half-up, up or down
+    #[clap(long, default_value = "half-even")]
+    rounding: RoundingMode,
+    /// Adds to the output the client's funds consolidated in this currency, requires --rates
+    #[clap(long, requires = "rates")]
+    base_currency: Option<Currency>,
+    /// JSON file with the interest rates of every client tier, interest is accrued after processing the input
+    #[clap(long, requires_all = &["interest-from", "interest-to"])]
+    interest: Option<PathBuf>,
+    /// Timestamp when the interest accrual period starts
+    #[clap(long)]
+    interest_from: Option<Timestamp>,
+    /// Timestamp when the interest accrual period ends
+    #[clap(long)]
+    interest_to: Option<Timestamp>,
+    /// CSV file where the interest postings are written
+    #[clap(long, requires = "interest")]
+    interest_postings: Option<PathBuf>,
+    /// Funds restored when a chargeback is represented: full or requested
+    #[clap(long, default_value = "full")]
+    representment_restore: RestoreFunds,
+    /// Unfreezes an account once every chargeback that froze it has been represented
+    #[clap(long)]
+    unfreeze_on_representment: bool,
+    /// Comma separated reason codes of the chargebacks that freeze the account, by default every chargeback does
+    #[clap(long, use_value_delimiter = true)]
+    freeze_reasons: Option<Vec<Reason>>,
+    /// Skips the records that cannot be read instead of aborting the run
+    #[clap(long)]
+    lenient: bool,
+    /// CSV file where the records that cannot be read are written along with their error, requires --lenient
+    #[clap(long, requires = "lenient")]
+    quarantine: Option<PathBuf>,
+    /// Maximum number of records that cannot be read before aborting the run, requires --lenient
+    #[clap(long, requires = "lenient")]
+    max_errors: Option<u64>,
+    /// Maximum fraction of records that cannot be read before aborting the run, requires --lenient
+    #[clap(long, requires = "lenient")]
+    max_error_ratio: Option<f64>,
+    /// JSON file where the processed input and the ledger are checkpointed, processing resumes from it when it exists
+    #[clap(long)]
+    checkpoint: Option<PathBuf>,
+    /// Number of processed records between checkpoints
+    #[clap(long, default_value = "10000", requires = "checkpoint")]
+    checkpoint_every: u64,
+    /// Binary transaction log where the transactions read from the inputs are written, for replaying them faster
+    #[clap(long)]
+    write_binary: Option<PathBuf>,
+    /// Format of the ledger written to stdout: csv or json
+    #[clap(long, default_value = "csv")]
+    output: Output,
+    /// Number of worker threads applying the transactions, every one owning the accounts of a shard of the clients
+    #[clap(long, default_value = "1", conflicts_with_all = &["checkpoint", "follow"])]
+    shards: usize,
+    /// Keeps tailing the input file, processing the records appended to it and emitting the ledger periodically
+    #[clap(long, conflicts_with_all = &["checkpoint", "compress", "max-error-ratio"])]
+    follow: bool,
+    /// Milliseconds between reads of the followed file
+    #[clap(long, default_value = "1000", requires = "follow")]
+    poll_interval: u64,
+    /// Seconds between emissions of the ledger while following, when it has changed
+    #[clap(long, default_value = "10", requires = "follow")]
+    emit_every: u64,
+    /// What is emitted while following: snapshot, every account, or deltas, the accounts changed since the last emission
+    #[clap(long, default_value = "snapshot", requires = "follow")]
+    emit: Emit,
+    /// Compression of the ledger and of the CSV files written: none, gzip or zstd
+    #[clap(long, default_value = "none")]
+    compress: Compression,
+    /// Timestamp every transaction is processed at for evaluating the limits and conversions, by default the time
+    /// the run starts. Follow mode uses the time every transaction is read instead
+    #[clap(long, conflicts_with = "follow")]
+    as_of: Option<Timestamp>,
+}
+
+/// Parses the `--spread` argument, rejecting the spreads the converter doesn't accept.
+fn parse_spread(spread: &str) -> Result<f64> {
+    let spread = spread.parse()?;
+    Fx::default().with_spread(spread)?;
+    Ok(spread)
+}
+
+/// Formats of the ledger written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Csv,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Output> {
+        match s {
+            "csv" => Ok(Output::Csv),
+            "json" => Ok(Output::Json),
+            _ => Err(anyhow::anyhow!("Not a valid output format '{}'", s)),
+        }
+    }
+}
+
+/// Creates the sink writing the ledger in the format selected through `--output`.
+fn ledger_sink<'a>(output: Output, writer: Box<dyn Write + 'a>) -> Box<dyn LedgerSink + 'a> {
+    match output {
+        Output::Csv => Box::new(CsvSink::new(writer)),
+        Output::Json => Box::new(JsonSink::new(writer)),
+    }
+}
+
+/// Accounts emitted periodically in follow mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emit {
+    Snapshot,
+    Deltas,
+}
+
+impl FromStr for Emit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Emit> {
+        match s {
+            "snapshot" => Ok(Emit::Snapshot),
+            "deltas" => Ok(Emit::Deltas),
+            _ => Err(anyhow::anyhow!("Not a valid emit mode '{}'", s)),
+        }
+    }
+}
+
+/// Columns of the CSV file of rejected transactions.
+const REJECTION_COLUMNS: [&str; 8] = [
+    "type",
+    "client",
+    "tx",
+    "reason",
+    "dispute_reason",
+    "code",
+    "file",
+    "position",
+];
+
+/// Statistics of the records read from an input file and of their processing.
+#[derive(Debug, Default)]
+struct FileStats {
+    file: String,
+    records: u64,
+    malformed: u64,
+    skipped: u64,
+    applied: u64,
+    rejected: u64,
+}
+
+/// Expands the glob patterns of the inputs, keeping `-` and the rest of inputs as they are.
+fn expand_inputs(inputs: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        if input == "-" || !input.contains(['*', '?', '[']) {
+            expanded.push(input.clone());
+            continue;
+        }
+        let mut paths = glob::glob(input)?.collect::<Result<Vec<_>, _>>()?;
+        if paths.is_empty() {
+            anyhow::bail!("no input files matching '{}'", input);
+        }
+        paths.sort();
+        expanded.extend(paths.iter().map(|path| path.display().to_string()));
+    }
+    Ok(expanded)
+}
+
+/// Opens an input file, or stdin for `-`, decompressing it when needed.
+fn open_input(input: &str) -> Result<Box<dyn std::io::Read>> {
+    if input == "-" {
+        decompress(std::io::stdin().lock())
+    } else {
+        decompress(std::fs::File::open(input)?)
+    }
+}
+
+/// Formats of the input files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Ndjson,
+    Binary,
+}
+
+impl Format {
+    /// Tells the format of an input from its first bytes, or from its extension for NDJSON.
+    fn detect(input: &str, head: &[u8]) -> Format {
+        let name = input.trim_end_matches(".gz").trim_end_matches(".zst");
+        if is_binary_log(head) {
+            Format::Binary
+        } else if name.ends_with(".ndjson") || name.ends_with(".jsonl") {
+            Format::Ndjson
+        } else {
+            Format::Csv
+        }
+    }
+}
+
+/// Opens the source of the transactions of an input file, or stdin for `-`, in its format.
+///
+/// CSV inputs parsed on several threads are read as a whole beforehand, with `lenient` set the records that cannot
+/// be read are then counted in the statistics of the file and quarantined, as long as they fit in the `budget`.
+fn open_source(
+    file_stats: &mut FileStats,
+    config: &ReaderConfig,
+    lenient: bool,
+    budget: &ErrorBudget,
+    mut quarantine: Option<&mut CsvOutput>,
+) -> Result<Box<dyn TransactionSource>> {
+    let file = file_stats.file.clone();
+    let mut input = open_input(&file)?;
+    // pipes and decompressed streams may hand over fewer bytes than the magic ones in a single read
+    let mut head = Vec::with_capacity(BINARY_MAGIC_LEN);
+    (&mut input)
+        .take(BINARY_MAGIC_LEN as u64)
+        .read_to_end(&mut head)?;
+    let format = Format::detect(&file, &head);
+    let reader = std::io::BufReader::new(std::io::Cursor::new(head).chain(input));
+    Ok(match format {
+        Format::Binary => Box::new(BinarySource::new(&file, reader)?),
+        Format::Ndjson => Box::new(NdjsonSource::new(&file, reader)),
+        Format::Csv if config.threads > 1 && lenient => {
+            let transactions = load_csv_transactions_lenient(reader, config, budget, |err| {
+                file_stats.malformed += 1;
+                quarantine_record(quarantine.as_deref_mut(), err, &file)
+            })?;
+            Box::new(MemorySource::new(&file, transactions))
+        }
+        Format::Csv if config.threads > 1 => Box::new(MemorySource::new(
+            &file,
+            load_csv_transactions_with(reader, config)?,
+        )),
+        Format::Csv => Box::new(CsvSource::with_config(&file, reader, config.clone())),
+    })
+}
+
+/// Binary transaction log written through `--write-binary`.
+type BinaryOutput = BinaryWriter<std::io::BufWriter<std::fs::File>>;
+
+/// Hands the transactions of an input over to the engine one at a time, writing them to the binary log and
+/// skipping the ones processed before the checkpoint being resumed on the way.
+///
+/// It ends once `left` transactions have been handed over, so that the engine can be checkpointed in between, and
+/// `exhausted` tells whether the input has been read up to its end.
+struct Reading<'a> {
+    source: Box<dyn TransactionSource>,
+    binary: Option<&'a mut BinaryOutput>,
+    resume: Option<&'a mut Verifier>,
+    /// Records read, including the ones that cannot be read.
+    records: u64,
+    /// Transactions skipped as they were processed before the checkpoint.
+    skipped: u64,
+    /// Highest transaction id read.
+    last_tx: Tx,
+    left: u64,
+    exhausted: bool,
+}
+
+impl<'a> Reading<'a> {
+    fn new(
+        source: Box<dyn TransactionSource>,
+        binary: Option<&'a mut BinaryOutput>,
+        resume: Option<&'a mut Verifier>,
+    ) -> Reading<'a> {
+        Reading {
+            source,
+            binary,
+            resume,
+            records: 0,
+            skipped: 0,
+            last_tx: 0,
+            left: u64::MAX,
+            exhausted: false,
+        }
+    }
+
+    /// Writes a transaction read to the binary log, and tells whether it must be skipped.
+    fn read(&mut self, tx: &Transaction) -> Result<bool> {
+        self.last_tx = self.last_tx.max(tx.id());
+        if let Some(writer) = self.binary.as_mut() {
+            writer.write(tx)?;
+        }
+        match self.resume.as_mut() {
+            Some(resume) => resume.skip(tx),
+            None => Ok(false),
+        }
+    }
+}
+
+impl TransactionSource for Reading<'_> {
+    fn name(&self) -> &str {
+        self.source.name()
+    }
+
+    fn next_transaction(&mut self) -> Option<(Result<Transaction>, Provenance)> {
+        while self.left > 0 {
+            let (tx, provenance) = match self.source.next_transaction() {
+                Some(next) => next,
+                None => {
+                    self.exhausted = true;
+                    return None;
+                }
+            };
+            self.records += 1;
+            let tx = match tx {
+                Ok(tx) => tx,
+                Err(err) => return Some((Err(err), provenance)),
+            };
+            match self.read(&tx) {
+                Ok(true) => self.skipped += 1,
+                Ok(false) => {
+                    self.left -= 1;
+                    return Some((Ok(tx), provenance));
+                }
+                Err(err) => return Some((Err(err), provenance)),
+            }
+        }
+        None
+    }
+}
+
+/// The engine of a batch run, sharded through `--shards`. Sharded engines only hand the rejected transactions
+/// back once finished, so the input and position of every transaction are kept until then.
+enum Processor {
+    Single(Engine),
+    Sharded(ShardedEngine, Vec<(usize, u64)>),
+}
+
+/// The state of a batch run shared by its inputs.
+struct Run<'a> {
+    args: &'a Args,
+    config: &'a ReaderConfig,
+    budget: &'a ErrorBudget,
+    /// Name of the whole input in the checkpoints.
+    input: &'a str,
+    stats: Vec<FileStats>,
+    quarantine: Option<CsvOutput>,
+    rejections: Option<CsvOutput>,
+    binary: Option<BinaryOutput>,
+    resume: Option<Verifier>,
+    last_tx: Tx,
+}
+
+impl Run<'_> {
+    /// Processes the transactions of the input at `index` as they are read, saving a checkpoint every
+    /// `--checkpoint-every` transactions when requested.
+    fn process_input(&mut self, index: usize, processor: &mut Processor) -> Result<()> {
+        let (lenient, budget) = (self.args.lenient, self.budget);
+        let file_stats = &mut self.stats[index];
+        let source = open_source(
+            file_stats,
+            self.config,
+            lenient,
+            budget,
+            self.quarantine.as_mut(),
+        )?;
+        let loaded = file_stats.malformed;
+        let mut reading = Reading::new(source, self.binary.as_mut(), self.resume.as_mut());
+        let (quarantine, rejections) = (&mut self.quarantine, &mut self.rejections);
+        match processor {
+            Processor::Sharded(sharded, origins) => {
+                while let Some((tx, provenance)) = reading.next_transaction() {
+                    match tx {
+                        Ok(tx) => {
+                            sharded.process(tx)?;
+                            origins.push((index, provenance.position));
+                        }
+                        Err(err) => {
+                            let quarantine = quarantine.as_mut();
+                            malformed(err, &provenance, file_stats, lenient, budget, quarantine)?
+                        }
+                    }
+                }
+            }
+            Processor::Single(engine) => {
+                let every = self.args.checkpoint_every.max(1);
+                while !reading.exhausted {
+                    // the transactions are run in batches ending at every checkpoint, or all at once without them
+                    if self.args.checkpoint.is_some() {
+                        reading.left = every - engine.processed() % every;
+                    }
+                    engine.run(&mut reading, |tx, provenance, err| match tx {
+                        Some(tx) => reject(file_stats, tx, &err, provenance, rejections.as_mut()),
+                        None => {
+                            let quarantine = quarantine.as_mut();
+                            malformed(err, provenance, file_stats, lenient, budget, quarantine)
+                        }
+                    })?;
+                    match &self.args.checkpoint {
+                        Some(path) if !reading.exhausted => {
+                            save_checkpoint(engine, self.input, path, rejections.as_mut())?
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        file_stats.records = reading.records + loaded;
+        file_stats.skipped = reading.skipped;
+        self.last_tx = self.last_tx.max(reading.last_tx);
+        budget.check_ratio(file_stats.malformed, file_stats.records)
+    }
+}
+
+/// A CSV file written by the application, compressed as requested through `--compress`.
+type CsvOutput = csv::Writer<CompressedWriter<std::fs::File>>;
+
+/// Creates a CSV writer over a file, compressing it as requested through `--compress`.
+fn csv_output(file: std::fs::File, compression: Compression) -> Result<CsvOutput> {
+    Ok(csv::Writer::from_writer(compress(file, compression)?))
+}
+
+/// Flushes a CSV file and finishes its compressed stream, reporting any error of the last writes.
+fn finish_csv(writer: CsvOutput) -> Result<()> {
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .finish()?;
+    Ok(())
+}
+
+/// Writes a rejected transaction along with its rejection reason, the file it was read from and its position in
+/// it, when known.
+fn write_rejection(
+    writer: &mut CsvOutput,
+    tx: &Transaction,
+    err: &anyhow::Error,
+    file: &str,
+    position: Option<u64>,
+) -> Result<()> {
+    writer.write_record([
+        tx.type_name().to_string(),
+        tx.client_id().to_string(),
+        tx.id().to_string(),
+        err.to_string(),
+        tx.reason().cloned().unwrap_or_default(),
+        err.downcast_ref::<ValidationError>()
+            .map_or("", |err| err.code())
+            .to_string(),
+        file.to_string(),
+        position.map_or(String::new(), |position| position.to_string()),
+    ])?;
+    Ok(())
+}
+
+/// Reopens the rejections written before a crash, keeping only the first `written` bytes of them, written by the
+/// time of the checkpoint being resumed, as the records after it are processed again.
+fn resume_rejections(
+    path: &std::path::Path,
+    written: Option<u64>,
+    compression: Compression,
+) -> Result<CsvOutput> {
+    let mut kept = Vec::new();
+    if let Some(written) = written {
+        decompress(std::fs::File::open(path)?)?
+            .take(written)
+            .read_to_end(&mut kept)?;
+        if (kept.len() as u64) < written {
+            anyhow::bail!(
+                "resuming failed, the rejections have {} bytes but {} were written by the checkpoint.",
+                kept.len(),
+                written
+            );
+        }
+    }
+    let mut writer = compress(std::fs::File::create(path)?, compression)?;
+    writer.write_all(&kept)?;
+    let mut writer = csv::Writer::from_writer(writer);
+    if kept.is_empty() {
+        writer.write_record(REJECTION_COLUMNS)?;
+    }
+    Ok(writer)
+}
+
+/// Saves a checkpoint of the engine, recording how much of the rejections had been written by then.
+fn save_checkpoint(
+    engine: &Engine,
+    input: &str,
+    path: &std::path::Path,
+    rejections: Option<&mut CsvOutput>,
+) -> Result<()> {
+    let mut checkpoint = engine.checkpoint(input);
+    if let Some(writer) = rejections {
+        writer.flush()?;
+        checkpoint.rejections = Some(writer.get_ref().written());
+    }
+    checkpoint.save(path)
+}
+
+/// Logs a record that cannot be read, and writes it along with its error to the quarantine if requested.
+fn quarantine_record(
+    quarantine: Option<&mut CsvOutput>,
+    err: &ReaderError,
+    file: &str,
+) -> Result<()> {
+    warn!("Record quarantined from {}: {}", file, err);
+    if let Some(writer) = quarantine {
+        writer.write_record([
+            err.line.to_string(),
+            err.byte.to_string(),
+            err.field_name.clone().unwrap_or_default(),
+            err.record.clone(),
+            err.message.clone(),
+            file.to_string(),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Handles a record that cannot be read: with `--lenient` it's counted in the statistics of its file and
+/// quarantined as long as it fits in the `budget`, otherwise it stops the run.
+fn malformed(
+    err: anyhow::Error,
+    provenance: &Provenance,
+    file_stats: &mut FileStats,
+    lenient: bool,
+    budget: &ErrorBudget,
+    quarantine: Option<&mut CsvOutput>,
+) -> Result<()> {
+    match err.downcast_ref::<ReaderError>() {
+        Some(malformed) if lenient => {
+            file_stats.malformed += 1;
+            quarantine_record(quarantine, malformed, &provenance.source)?;
+            budget.check_errors(file_stats.malformed)
+        }
+        _ => Err(err),
+    }
+}
+
+/// Counts a rejected transaction in the statistics of its file, and writes it along with its provenance to the
+/// rejections if requested.
+fn reject(
+    file_stats: &mut FileStats,
+    tx: &Transaction,
+    err: &anyhow::Error,
+    provenance: &Provenance,
+    rejections: Option<&mut CsvOutput>,
+) -> Result<()> {
+    file_stats.rejected += 1;
+    info!(
+        "Transaction rejected from {}:{}: {:?}, reason: {}",
+        provenance.source, provenance.position, tx, err
+    );
+    if let Some(writer) = rejections {
+        write_rejection(
+            writer,
+            tx,
+            err,
+            &provenance.source,
+            Some(provenance.position),
+        )?;
+    }
+    Ok(())
+}
+
+/// Tails the input file, processing the records appended to it and writing the ledger to stdout every
+/// `--emit-every` seconds when it has changed. It runs until the process is stopped.
+///
+/// Records that cannot be read stop it like any other run, unless `--lenient` is given and they fit in the
+/// `budget`.
+fn follow(
+    args: &Args,
+    mut engine: Engine,
+    file: &str,
+    config: ReaderConfig,
+    budget: &ErrorBudget,
+    mut rejections: Option<CsvOutput>,
+    mut quarantine: Option<CsvOutput>,
+) -> Result<()> {
+    let mut follower = Follower::new(file, config);
+    let mut sink = ledger_sink(args.output, Box::new(std::io::stdout()));
+    let mut changed = BTreeSet::new();
+    let mut malformed = 0;
+    let mut emitted = Instant::now();
+    loop {
+        for read in follower.poll()? {
+            let tx = match read {
+                Ok(tx) => tx,
+                Err(err) if args.lenient => {
+                    malformed += 1;
+                    quarantine_record(quarantine.as_mut(), &err, file)?;
+                    budget.check_errors(malformed)?;
+                    continue;
+                }
+                Err(err) => {
+                    eprintln!("{}", err.diagnostic(file));
+                    // exiting runs no destructors, the rejections written so far must be flushed beforehand
+                    if let Some(writer) = rejections.take() {
+                        finish_csv(writer)?;
+                    }
+                    std::process::exit(1);
+                }
+            };
+            match engine.process(tx.clone()) {
+                Ok(()) => {
+                    changed.insert(tx.client_id());
+                }
+                Err(err) => {
+                    info!(
+                        "Transaction rejected from {}: {:?}, reason: {}",
+                        file, tx, err
+                    );
+                    if let Some(writer) = rejections.as_mut() {
+                        write_rejection(writer, &tx, &err, file, None)?;
+                    }
+                }
+            }
+        }
+        if !changed.is_empty() && emitted.elapsed() >= Duration::from_secs(args.emit_every) {
+            let ledger: Ledger = engine
+                .ledger()
+                .iter()
+                .filter(|(client_id, _)| {
+                    args.emit == Emit::Snapshot || changed.contains(*client_id)
+                })
+                .map(|(client_id, account)| (*client_id, account.clone()))
+                .collect();
+            report_ledger(&ledger, sink.as_mut())?;
+            for writer in rejections.iter_mut().chain(quarantine.iter_mut()) {
+                writer.flush()?;
+            }
+            changed.clear();
+            emitted = Instant::now();
+        }
+        std::thread::sleep(Duration::from_millis(args.poll_interval));
+    }
+}
 
 /// function that starts the payment application leveraging all of the tools provided by the payments-core crate.
 fn main() -> Result<()> {
     env_logger::init();
 
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        return Err(anyhow!("Missing file name!"));
-    }
-    let mut ledger = Ledger::new();
+    let args = Args::parse();
 
-    let file = std::path::Path::new(&args[1]);
-    let input_file = std::fs::File::open(file)?;
-    let transactions =
-        payments_core::load_csv_transactions(input_file).expect("Failed loading csv transactions!");
+    let mut engine = Engine::new().with_policy(Policy {
+        restore_funds: args.representment_restore,
+        unfreeze_on_representment: args.unfreeze_on_representment,
+        freeze_reasons: args.freeze_reasons.as_ref().map(|reasons| {
+            reasons
+                .iter()
+                .map(|reason| reason.trim().to_lowercase())
+                .collect()
+        }),
+    });
+    if let Some(path) = &args.limits {
+        engine = engine.with_limits(Limits::from_reader(std::fs::File::open(path)?)?);
+    }
+    let fx = match &args.rates {
+        Some(path) => {
+            let rates = RateTable::from_reader(std::fs::File::open(path)?)?;
+            let fx = Fx::new(rates)
+                .with_spread(args.spread)?
+                .with_rounding(Rounding {
+                    decimals: args.decimals,
+                    mode: args.rounding,
+                });
+            engine = engine.with_fx(fx.clone());
+            Some(fx)
+        }
+        None => None,
+    };
+    let inputs = expand_inputs(&args.inputs)?;
+    let input = inputs.join(",");
+    let quarantine = match &args.quarantine {
+        Some(path) => {
+            let mut writer = csv_output(std::fs::File::create(path)?, args.compress)?;
+            writer.write_record(["line", "byte", "field", "record", "error", "file"])?;
+            Some(writer)
+        }
+        None => None,
+    };
+    let mut config = match &args.reader_config {
+        Some(path) => ReaderConfig::from_reader(std::fs::File::open(path)?)?,
+        None => ReaderConfig::default(),
+    };
+    if let Some(threads) = args.threads {
+        config.threads = threads;
+    }
+    let budget = ErrorBudget {
+        max_errors: args.max_errors,
+        max_ratio: args.max_error_ratio,
+    };
 
-    for tx in transactions {
-        let client_id = match tx {
-            Transaction::Deposit(client_id, _, _)
-            | Transaction::Withdrawal(client_id, _, _)
-            | Transaction::Dispute(client_id, _, _)
-            | Transaction::Resolve(client_id, _, _)
-            | Transaction::Chargeback(client_id, _, _) => client_id,
+    if args.follow {
+        let file = match inputs.as_slice() {
+            [file] if file != "-" => file,
+            _ => anyhow::bail!("follow mode requires a single input file"),
+        };
+        let rejections = match &args.rejections {
+            Some(path) => {
+                let mut writer = csv_output(std::fs::File::create(path)?, args.compress)?;
+                writer.write_record(REJECTION_COLUMNS)?;
+                Some(writer)
+            }
+            None => None,
         };
+        return follow(&args, engine, file, config, &budget, rejections, quarantine);
+    }
+    // the inputs carry no timestamps, so all of them are processed at the same time whenever they are replayed
+    let as_of = match args.as_of {
+        Some(as_of) => as_of,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    engine = engine.with_as_of(as_of);
+
+    let (mut resume, mut written) = (None, None);
+    if let Some(path) = args.checkpoint.as_ref().filter(|path| path.exists()) {
+        let checkpoint = Checkpoint::from_reader(std::fs::File::open(path)?)?;
+        resume = Some(checkpoint.verifier(&input)?);
+        written = checkpoint.rejections;
+        info!(
+            "Resuming {} after {} processed records",
+            input, checkpoint.records
+        );
+        engine = engine.with_checkpoint(checkpoint);
+    }
+    let rejections = match &args.rejections {
+        Some(path) if resume.is_some() => Some(resume_rejections(path, written, args.compress)?),
+        Some(path) => {
+            let mut writer = csv_output(std::fs::File::create(path)?, args.compress)?;
+            writer.write_record(REJECTION_COLUMNS)?;
+            Some(writer)
+        }
+        None => None,
+    };
+    let binary = match &args.write_binary {
+        Some(path) => Some(BinaryWriter::new(std::io::BufWriter::new(
+            std::fs::File::create(path)?,
+        ))?),
+        None => None,
+    };
 
-        // create a new account if not already present
-        if !ledger.contains_key(&client_id) {
-            ledger.insert(client_id, Account::new(client_id));
+    let mut processor = match args.shards {
+        0 | 1 => Processor::Single(engine),
+        shards => Processor::Sharded(ShardedEngine::new(engine, shards), Vec::new()),
+    };
+    let mut run = Run {
+        args: &args,
+        config: &config,
+        budget: &budget,
+        input: &input,
+        stats: inputs
+            .iter()
+            .map(|file| FileStats {
+                file: file.clone(),
+                ..FileStats::default()
+            })
+            .collect(),
+        quarantine,
+        rejections,
+        binary,
+        resume,
+        last_tx: 0,
+    };
+    for index in 0..inputs.len() {
+        if let Err(err) = run.process_input(index, &mut processor) {
+            let file = &run.stats[index].file;
+            match err.downcast_ref::<ReaderError>() {
+                Some(err) => eprintln!("{}", err.diagnostic(file)),
+                None => eprintln!("error: {}: {}", file, err),
+            }
+            // exiting runs no destructors, the records written so far must be written beforehand
+            for writer in run
+                .quarantine
+                .take()
+                .into_iter()
+                .chain(run.rejections.take())
+            {
+                finish_csv(writer)?;
+            }
+            std::process::exit(1);
         }
+    }
+    if let Some(resume) = &run.resume {
+        resume.finish()?;
+    }
+    let Run {
+        mut stats,
+        quarantine,
+        mut rejections,
+        binary,
+        last_tx,
+        ..
+    } = run;
+    if let Some(writer) = quarantine {
+        finish_csv(writer)?;
+    }
+    if let (Some(path), Some(writer)) = (&args.write_binary, binary) {
+        writer.finish()?;
+        let written: u64 = stats
+            .iter()
+            .map(|file_stats| file_stats.records - file_stats.malformed)
+            .sum();
+        info!("Wrote {} transactions to {}", written, path.display());
+    }
 
-        let account = ledger.get_mut(&client_id).expect("Failed getting account!");
-        debug!("Account before: {}, tx: {:?}", account.to_string(), tx);
+    let mut ledger = match processor {
+        Processor::Sharded(sharded, origins) => {
+            let (engine, rejected) = sharded.finish()?;
+            for Rejected { index, tx, err } in rejected {
+                let (input, position) = origins[index as usize];
+                let file_stats = &mut stats[input];
+                let provenance = Provenance {
+                    source: file_stats.file.as_str().into(),
+                    position,
+                };
+                reject(file_stats, &tx, &err, &provenance, rejections.as_mut())?;
+            }
+            engine.into_ledger()
+        }
+        Processor::Single(engine) => {
+            if let Some(path) = &args.checkpoint {
+                save_checkpoint(&engine, &input, path, rejections.as_mut())?;
+            }
+            engine.into_ledger()
+        }
+    };
+    if let Some(writer) = rejections {
+        finish_csv(writer)?;
+    }
+    for file_stats in stats.iter_mut() {
+        file_stats.applied =
+            file_stats.records - file_stats.malformed - file_stats.skipped - file_stats.rejected;
+    }
 
-        match account.process(tx) {
-            _ => (),
+    for file_stats in &stats {
+        info!("Processed {:?}", file_stats);
+    }
+    if let Some(path) = &args.summary {
+        let mut writer = csv_output(std::fs::File::create(path)?, args.compress)?;
+        writer.write_record([
+            "file",
+            "records",
+            "malformed",
+            "skipped",
+            "applied",
+            "rejected",
+        ])?;
+        for file_stats in &stats {
+            writer.serialize((
+                &file_stats.file,
+                file_stats.records,
+                file_stats.malformed,
+                file_stats.skipped,
+                file_stats.applied,
+                file_stats.rejected,
+            ))?;
         }
-        debug!("Account after: {}", account.to_string());
+        finish_csv(writer)?;
     }
 
-    print_ledger(ledger);
+    if let (Some(path), Some(from), Some(to)) =
+        (&args.interest, args.interest_from, args.interest_to)
+    {
+        let rates = InterestRates::from_reader(std::fs::File::open(path)?)?;
+        let first_tx = last_tx.checked_add(1).ok_or_else(|| {
+            anyhow::anyhow!("interest accrual failed, the inputs use the last transaction id.")
+        })?;
+        let posted = accrue_interest(&mut ledger, &rates, from, to, first_tx)?;
+        if let Some(path) = &args.interest_postings {
+            let mut writer = csv_output(std::fs::File::create(path)?, args.compress)?;
+            writer.write_record(["type", "client", "tx", "amount", "currency"])?;
+            for posting in posted {
+                if let Transaction::Interest(client_id, tx, amount, currency) = posting {
+                    writer.serialize((posting.type_name(), client_id, tx, amount, currency))?;
+                }
+            }
+            finish_csv(writer)?;
+        }
+    }
+
+    let mut stdout = compress(std::io::stdout().lock(), args.compress)?;
+    let mut sink = ledger_sink(args.output, Box::new(&mut stdout));
+    match (&fx, args.base_currency) {
+        (Some(fx), Some(base)) => {
+            report_consolidated_ledger(&ledger, fx, base, as_of, sink.as_mut())?;
+        }
+        _ => report_ledger(&ledger, sink.as_mut())?,
+    }
+    drop(sink);
+    stdout.finish()?;
     Ok(())
 }